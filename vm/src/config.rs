@@ -0,0 +1,21 @@
+use crate::interpreter::TAPE_SIZE;
+
+/// Machine parameters the interpreter runs with. The circuit must be
+/// configured with the same values to accept the resulting trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VmConfig {
+    /// Number of memory cells on the tape.
+    pub tape_size: usize,
+    /// Width of a memory cell in bits, with `+` and `-` wrapping around.
+    /// `None` keeps cells as unbounded field elements.
+    pub cell_bits: Option<u32>,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            tape_size: TAPE_SIZE,
+            cell_bits: None,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+/// Why a program cannot be compiled or run to completion.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VmError {
+    /// The configured tape has no cells.
+    EmptyTape,
+    /// A byte of the code is not one of the eight instructions.
+    InvalidInstruction { position: usize, byte: u8 },
+    /// A bracket has no partner, at a position of the compiled program.
+    UnmatchedBracket { position: usize },
+    /// The compiled program does not fit single-byte jump targets.
+    ProgramTooLong { len: usize },
+    /// `<` on the first cell of the tape.
+    TapeUnderflow { clk: u64 },
+    /// `>` on the last cell of the tape.
+    TapeOverflow { clk: u64 },
+    /// `,` with no input left.
+    InputExhausted { clk: u64 },
+    /// `,` reading a value that does not fit a cell, printed in hex.
+    InputOutOfRange { clk: u64, value: String },
+    /// The program did not halt within the step budget.
+    StepLimit { max_steps: usize },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::EmptyTape => write!(f, "the tape needs at least one cell"),
+            VmError::InvalidInstruction { position, byte } => {
+                write!(f, "byte {byte:#04x} at {position} is not an instruction")
+            }
+            VmError::UnmatchedBracket { position } => {
+                write!(f, "bracket at {position} is unmatched")
+            }
+            VmError::ProgramTooLong { len } => {
+                write!(f, "compiled program of {len} bytes is too long")
+            }
+            VmError::TapeUnderflow { clk } => {
+                write!(f, "step {clk} moves left of the first cell")
+            }
+            VmError::TapeOverflow { clk } => {
+                write!(f, "step {clk} moves right of the last cell")
+            }
+            VmError::InputExhausted { clk } => write!(f, "step {clk} reads past the input"),
+            VmError::InputOutOfRange { clk, value } => {
+                write!(f, "step {clk} reads {value}, which does not fit a cell")
+            }
+            VmError::StepLimit { max_steps } => {
+                write!(f, "program did not halt within {max_steps} steps")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
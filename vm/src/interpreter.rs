@@ -1,22 +1,29 @@
 use std::mem;
 
-use halo2_proofs::halo2curves::bn256::Fr;
+use halo2_proofs::{
+    arithmetic::Field,
+    halo2curves::{bn256::Fr, ff::PrimeField},
+};
 
 use crate::{
+    config::VmConfig,
+    error::VmError,
     register::{self, Registers},
     table::{
         InputTableRow, InstructionTableRow, MemoryTableRow, OutputTableRow, ProcessTableRow, Tables,
     },
 };
 
-pub struct Interpreter {
+pub struct Interpreter<F = Fr> {
     code: Vec<u8>,
     program: Vec<u8>,
-    registers: Registers,
-    pub tables: Tables,
-    memory: Vec<Fr>,
-    input: Vec<Fr>,
-    output: Vec<Fr>,
+    registers: Registers<F>,
+    pub tables: Tables<F>,
+    memory: Vec<F>,
+    input: Vec<F>,
+    output: Vec<F>,
+    /// `2^cell_bits` when cells wrap around
+    cell_modulus: Option<F>,
 }
 pub const SHL: u8 = 60;
 pub const SHR: u8 = 62;
@@ -26,26 +33,67 @@ pub const GETCHAR: u8 = 44;
 pub const PUTCHAR: u8 = 46;
 pub const LB: u8 = 91;
 pub const RB: u8 = 93;
+/// Number of memory cells on the tape.
+pub const TAPE_SIZE: usize = 50;
+/// Longest compiled program: jump targets are stored in a single byte.
+pub const MAX_PROGRAM_LEN: usize = 255;
 
-impl Interpreter {
-    pub fn new(code: Vec<u8>, input: Vec<Fr>) -> Self {
-        Self {
-            program: compile_code(&code),
+impl<F: PrimeField> Interpreter<F> {
+    pub fn new(code: Vec<u8>, input: Vec<F>) -> Self {
+        Self::with_config(code, input, VmConfig::default())
+    }
+    /// Panics if the code does not compile or the tape has no cells, see
+    /// `try_with_config`.
+    pub fn with_config(code: Vec<u8>, input: Vec<F>, config: VmConfig) -> Self {
+        Self::try_with_config(code, input, config).unwrap_or_else(|error| panic!("{error}"))
+    }
+    pub fn try_with_config(
+        code: Vec<u8>,
+        input: Vec<F>,
+        config: VmConfig,
+    ) -> Result<Self, VmError> {
+        if config.tape_size == 0 {
+            return Err(VmError::EmptyTape);
+        }
+        Ok(Self {
+            program: compile_code(&code)?,
             code,
             registers: Registers::default(),
             tables: Tables::default(),
-            memory: vec![Fr::zero(); 50],
+            memory: vec![F::ZERO; config.tape_size],
             input,
             output: Vec::new(),
-        }
+            cell_modulus: config
+                .cell_bits
+                .map(|bits| F::from(2).pow_vartime([bits as u64])),
+        })
+    }
+    /// The compiled program: the code with a jump target after each bracket.
+    pub fn program(&self) -> &[u8] {
+        &self.program
     }
+    /// Panics if the program fails, see `try_run`.
     pub fn run(&mut self) {
+        self.try_run(usize::MAX)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+    /// Runs the program for at most `max_steps` steps, the halt row
+    /// excluded, and builds the tables from its trace. On error the tables
+    /// are left incomplete.
+    pub fn try_run(&mut self, max_steps: usize) -> Result<(), VmError> {
         while self.registers.ip < self.program.len() {
+            if self.tables.processor_table.len() == max_steps {
+                return Err(VmError::StepLimit { max_steps });
+            }
             let instruction = self.program[self.registers.ip];
             self.registers.mv = self.memory[self.registers.mp];
-            self.registers.mvi = self.registers.mv.invert().unwrap_or(Fr::zero());
+            self.registers.mvi = self.registers.mv.invert().unwrap_or(F::ZERO);
             self.registers.ci = self.program[self.registers.ip];
-            self.registers.ni = self.program[self.registers.ip + 1];
+            self.registers.ni = self
+                .program
+                .get(self.registers.ip + 1)
+                .copied()
+                .unwrap_or(0);
 
             self.tables
                 .processor_table
@@ -53,23 +101,54 @@ impl Interpreter {
 
             match instruction {
                 SHL => {
+                    if self.registers.mp == 0 {
+                        return Err(VmError::TapeUnderflow {
+                            clk: self.registers.clk,
+                        });
+                    }
                     self.registers.mp -= 1;
                     self.registers.ip += 1;
                 }
                 SHR => {
+                    if self.registers.mp + 1 == self.memory.len() {
+                        return Err(VmError::TapeOverflow {
+                            clk: self.registers.clk,
+                        });
+                    }
                     self.registers.mp += 1;
                     self.registers.ip += 1;
                 }
                 ADD => {
-                    self.memory[self.registers.mp] += Fr::one();
+                    self.memory[self.registers.mp] += F::ONE;
+                    if Some(self.memory[self.registers.mp]) == self.cell_modulus {
+                        self.memory[self.registers.mp] = F::ZERO;
+                    }
                     self.registers.ip += 1;
                 }
                 SUB => {
-                    self.memory[self.registers.mp] -= Fr::one();
+                    if let Some(modulus) = self.cell_modulus {
+                        if self.memory[self.registers.mp] == F::ZERO {
+                            self.memory[self.registers.mp] = modulus;
+                        }
+                    }
+                    self.memory[self.registers.mp] -= F::ONE;
                     self.registers.ip += 1;
                 }
                 GETCHAR => {
+                    if self.input.is_empty() {
+                        return Err(VmError::InputExhausted {
+                            clk: self.registers.clk,
+                        });
+                    }
                     let input_num = self.input.remove(0);
+                    if let Some(modulus) = self.cell_modulus {
+                        if !lt_repr(&input_num, &modulus) {
+                            return Err(VmError::InputOutOfRange {
+                                clk: self.registers.clk,
+                                value: format!("{input_num:?}"),
+                            });
+                        }
+                    }
                     self.tables.input_table.push(InputTableRow {
                         clk: self.registers.clk,
                         value: input_num,
@@ -87,14 +166,14 @@ impl Interpreter {
                 }
                 LB => {
                     //program:: ++>,<[14>+.<-]7
-                    if self.registers.mv != Fr::zero() {
+                    if self.registers.mv != F::ZERO {
                         self.registers.ip += 2;
                     } else {
                         self.registers.ip = self.program[self.registers.ip + 1] as usize;
                     }
                 }
                 RB => {
-                    if self.registers.mv != Fr::zero() {
+                    if self.registers.mv != F::ZERO {
                         self.registers.ip = self.program[self.registers.ip + 1] as usize;
                     } else {
                         self.registers.ip += 2;
@@ -130,40 +209,33 @@ impl Interpreter {
             });
 
         //intruction table
-        self.tables.instruction_table = self
-            .tables
-            .processor_table
-            .iter()
-            .map(|row| InstructionTableRow {
-                ip: row.ip,
-                ci: row.ci,
-                ni: row.ni,
-            })
-            .collect();
-        //program:: ++>,<[14>+.<-]7
+        // Every position, jump operands included, so that ip advances by one
+        // between consecutive rows, then the halt position. The circuit looks
+        // each step up in this table, so steps need no rows of their own.
         let mut program = Vec::new();
         for (i, &value) in self.program.iter().enumerate() {
-            if is_insturction(value) {
-                let ni = if i + 1 < self.program.len() {
-                    self.program[i + 1]
-                } else {
-                    0
-                };
-                program.push(InstructionTableRow {
-                    ip: i,
-                    ci: value,
-                    ni,
-                })
-            }
+            let ni = if i + 1 < self.program.len() {
+                self.program[i + 1]
+            } else {
+                0
+            };
+            program.push(InstructionTableRow {
+                ip: i,
+                ci: value,
+                ni,
+            })
         }
+        program.push(InstructionTableRow {
+            ip: self.program.len(),
+            ci: 0,
+            ni: 0,
+        });
         // println!("program :{:?}", program);
-        self.tables.instruction_table.append(&mut program);
-        self.tables
-            .instruction_table
-            .sort_by(|a, b| a.ip.cmp(&b.ip));
+        self.tables.instruction_table = program;
         // println!("processor table:{:?}", self.tables.processor_table);
 
         // println!("instruction table:{:?}", self.tables.instruction_table);
+        Ok(())
     }
 }
 // fn fr_to_usize(num: Fr) -> usize {
@@ -172,33 +244,51 @@ impl Interpreter {
 //     usize::from_le_bytes(slice)
 // }
 
-fn compile_code(code: &[u8]) -> Vec<u8> {
+/// Integer comparison of two field elements through their little-endian
+/// canonical representation.
+fn lt_repr<F: PrimeField>(lhs: &F, rhs: &F) -> bool {
+    let lhs = lhs.to_repr();
+    let rhs = rhs.to_repr();
+    lhs.as_ref().iter().rev().cmp(rhs.as_ref().iter().rev()) == std::cmp::Ordering::Less
+}
+
+/// Compiles Brainfuck code, given as instruction bytes, into a program: the
+/// code with the position to jump to written after each bracket.
+pub fn compile_code(code: &[u8]) -> Result<Vec<u8>, VmError> {
     let mut program = Vec::new();
     let mut stack: Vec<usize> = Vec::new();
 
-    for &item in code.iter() {
+    for (position, &item) in code.iter().enumerate() {
+        if ![SHL, SHR, ADD, SUB, GETCHAR, PUTCHAR, LB, RB].contains(&item) {
+            return Err(VmError::InvalidInstruction {
+                position,
+                byte: item,
+            });
+        }
         program.push(item);
         if item == LB || item == RB {
             program.push(0);
         }
     }
+    if program.len() > MAX_PROGRAM_LEN {
+        return Err(VmError::ProgramTooLong { len: program.len() });
+    }
     for (index, &item) in program.clone().iter().enumerate() {
         if item == LB {
             stack.push(index);
         }
         if item == RB {
-            let lb_index = stack.pop();
-            if let Some(lb_index) = lb_index {
-                program[lb_index + 1] = index as u8 + 2;
-                program[index + 1] = lb_index as u8 + 2;
-            }
+            let Some(lb_index) = stack.pop() else {
+                return Err(VmError::UnmatchedBracket { position: index });
+            };
+            program[lb_index + 1] = index as u8 + 2;
+            program[index + 1] = lb_index as u8 + 2;
         }
     }
-    program
-}
-
-fn is_insturction(value: u8) -> bool {
-    matches!(value, SHL | SHR | ADD | SUB | GETCHAR | PUTCHAR | LB | RB)
+    if let Some(&position) = stack.last() {
+        return Err(VmError::UnmatchedBracket { position });
+    }
+    Ok(program)
 }
 
 #[cfg(test)]
@@ -216,6 +306,103 @@ mod tests {
         interpreter.run();
     }
 
+    fn cell_wraps<F: PrimeField>() {
+        let config = VmConfig {
+            cell_bits: Some(8),
+            ..VmConfig::default()
+        };
+        let mut interpreter =
+            Interpreter::<F>::with_config(vec![SUB, PUTCHAR, ADD, PUTCHAR], vec![], config);
+        interpreter.run();
+        let output = interpreter
+            .tables
+            .output_table
+            .iter()
+            .map(|row| row.value)
+            .collect::<Vec<_>>();
+        assert_eq!(output, vec![F::from(255), F::ZERO]);
+    }
+
+    #[test]
+    fn test_cell_wraps() {
+        cell_wraps::<Fr>();
+        cell_wraps::<halo2_proofs::halo2curves::pasta::Fp>();
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert_eq!(
+            compile_code(&[ADD, b'a']),
+            Err(VmError::InvalidInstruction {
+                position: 1,
+                byte: b'a'
+            })
+        );
+        assert_eq!(
+            compile_code(&[LB, LB, RB]),
+            Err(VmError::UnmatchedBracket { position: 0 })
+        );
+        assert_eq!(
+            compile_code(&[RB]),
+            Err(VmError::UnmatchedBracket { position: 0 })
+        );
+        assert_eq!(
+            compile_code(&[ADD; MAX_PROGRAM_LEN + 1]),
+            Err(VmError::ProgramTooLong {
+                len: MAX_PROGRAM_LEN + 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_run_errors() {
+        let config = VmConfig {
+            tape_size: 2,
+            cell_bits: Some(8),
+        };
+        let run = |code: Vec<u8>, max_steps| {
+            Interpreter::<Fr>::try_with_config(code, vec![], config)
+                .unwrap()
+                .try_run(max_steps)
+        };
+        assert_eq!(run(vec![SHL], 8), Err(VmError::TapeUnderflow { clk: 0 }));
+        assert_eq!(
+            run(vec![SHR, SHR], 8),
+            Err(VmError::TapeOverflow { clk: 1 })
+        );
+        assert_eq!(
+            run(vec![ADD, GETCHAR], 8),
+            Err(VmError::InputExhausted { clk: 1 })
+        );
+        assert_eq!(
+            run(vec![ADD, LB, RB], 8),
+            Err(VmError::StepLimit { max_steps: 8 })
+        );
+        assert_eq!(run(vec![ADD, ADD, ADD], 3), Ok(()));
+
+        let input = vec![Fr::from(255), Fr::from(256)];
+        let mut interpreter =
+            Interpreter::try_with_config(vec![GETCHAR, GETCHAR], input, config).unwrap();
+        assert_eq!(
+            interpreter.try_run(8),
+            Err(VmError::InputOutOfRange {
+                clk: 1,
+                value: format!("{:?}", Fr::from(256)),
+            })
+        );
+        assert!(matches!(
+            Interpreter::<Fr>::try_with_config(
+                vec![ADD],
+                vec![],
+                VmConfig {
+                    tape_size: 0,
+                    ..config
+                },
+            ),
+            Err(VmError::EmptyTape)
+        ));
+    }
+
     // #[test]
     // fn test_basic_operations() {
     //     let code = vec![ADD, ADD, SHR, SUB];
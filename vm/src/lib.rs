@@ -1,3 +1,5 @@
+pub mod config;
+pub mod error;
 pub mod interpreter;
 pub mod register;
 pub mod table;
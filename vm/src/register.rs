@@ -3,13 +3,13 @@ use std::default;
 use halo2_proofs::halo2curves::bn256::Fr;
 
 #[derive(Default, Clone, Debug)]
-pub struct Registers {
+pub struct Registers<F = Fr> {
     pub clk: u64,
     pub ip: usize,
     pub ci: u8,
     pub ni: u8,
     pub mp: usize,
-    pub mv: Fr,
-    pub mvi: Fr,
+    pub mv: F,
+    pub mvi: F,
 }
 //12 + 18+1 = 31
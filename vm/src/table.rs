@@ -5,26 +5,26 @@ use halo2_proofs::halo2curves::bn256::Fr;
 use crate::register::{self, Registers};
 
 #[derive(Default, Clone)]
-pub struct Tables {
-    pub processor_table: Vec<ProcessTableRow>,
-    pub memory_table: Vec<MemoryTableRow>,
+pub struct Tables<F = Fr> {
+    pub processor_table: Vec<ProcessTableRow<F>>,
+    pub memory_table: Vec<MemoryTableRow<F>>,
     pub instruction_table: Vec<InstructionTableRow>,
-    pub input_table: Vec<InputTableRow>,
-    pub output_table: Vec<OutputTableRow>,
+    pub input_table: Vec<InputTableRow<F>>,
+    pub output_table: Vec<OutputTableRow<F>>,
 }
 
-impl Tables {}
+impl<F> Tables<F> {}
 #[derive(Clone)]
-pub struct ProcessTableRow {
+pub struct ProcessTableRow<F = Fr> {
     pub clk: u64,
     pub ip: usize,
     pub ci: u8,
     pub ni: u8,
     pub mp: usize,
-    pub mv: Fr,
-    pub mvi: Fr,
+    pub mv: F,
+    pub mvi: F,
 }
-impl fmt::Debug for ProcessTableRow {
+impl<F: fmt::Debug> fmt::Debug for ProcessTableRow<F> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
             f,
@@ -33,8 +33,8 @@ impl fmt::Debug for ProcessTableRow {
         )
     }
 }
-impl From<Registers> for ProcessTableRow {
-    fn from(registers: Registers) -> Self {
+impl<F> From<Registers<F>> for ProcessTableRow<F> {
+    fn from(registers: Registers<F>) -> Self {
         Self {
             clk: registers.clk,
             ip: registers.ip,
@@ -47,12 +47,12 @@ impl From<Registers> for ProcessTableRow {
     }
 }
 #[derive(Clone)]
-pub struct MemoryTableRow {
+pub struct MemoryTableRow<F = Fr> {
     pub clk: u64,
     pub mp: usize,
-    pub mv: Fr,
+    pub mv: F,
 }
-impl fmt::Debug for MemoryTableRow {
+impl<F: fmt::Debug> fmt::Debug for MemoryTableRow<F> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
             f,
@@ -78,12 +78,12 @@ impl fmt::Debug for InstructionTableRow {
     }
 }
 #[derive(Clone)]
-pub struct InputTableRow {
+pub struct InputTableRow<F = Fr> {
     pub clk: u64,
-    pub value: Fr,
+    pub value: F,
 }
 #[derive(Clone)]
-pub struct OutputTableRow {
+pub struct OutputTableRow<F = Fr> {
     pub clk: u64,
-    pub value: Fr,
+    pub value: F,
 }
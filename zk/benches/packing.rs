@@ -0,0 +1,67 @@
+//! Proving time of a long-running program at different packing factors.
+//!
+//! Run with `cargo bench -p zk --bench packing --features insecure-setup`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand_core::OsRng;
+use vm::interpreter::{Interpreter, ADD, LB, RB, SHL, SHR, SUB};
+use zk::{
+    circuit::BrainfuckCircuit,
+    params::{BrainfuckParams, IoMode},
+    prover::{create, keygen, Backend, Kzg},
+    setup::KzgSource,
+    sizing::{params_k, MAX_K},
+};
+
+/// Counts 31 down to 0, adding 31 to a second cell each time: about a
+/// thousand steps.
+fn program() -> Vec<u8> {
+    let mut code = vec![ADD; 31];
+    code.extend([LB, SHR]);
+    code.extend([ADD; 31]);
+    code.extend([SHL, SUB, RB]);
+    code
+}
+
+fn bench_packing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prove");
+    group.sample_size(10);
+    for steps_per_row in [1, 2, 4] {
+        let params = BrainfuckParams {
+            max_program_len: 128,
+            max_steps: 1024,
+            io_mode: IoMode::None,
+            steps_per_row,
+            ..BrainfuckParams::default()
+        };
+        let mut interpreter = Interpreter::with_config(program(), vec![], params.vm_config());
+        interpreter.run();
+        let circuit = BrainfuckCircuit::new(interpreter.tables, params);
+
+        let k = params_k(&params, MAX_K).unwrap();
+        let srs = Kzg::load_setup(&KzgSource::InsecureLocal, k).unwrap();
+        let pk = keygen::<Kzg>(&srs, params).unwrap();
+
+        group.bench_with_input(
+            BenchmarkId::new(format!("k={k}"), steps_per_row),
+            &steps_per_row,
+            |b, _| {
+                b.iter(|| {
+                    create(
+                        &Kzg::default(),
+                        &srs,
+                        &pk,
+                        &circuit,
+                        circuit.instances(),
+                        OsRng,
+                    )
+                    .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_packing);
+criterion_main!(benches);
@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zk_fuzz::{interpret, Case};
+
+fuzz_target!(|case: Case| interpret(&case));
@@ -0,0 +1,134 @@
+//! Checks behind the fuzz targets, kept out of the targets so that saved
+//! failures can be replayed by `cargo test`.
+
+use arbitrary::Arbitrary;
+use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
+use vm::{
+    config::VmConfig,
+    interpreter::{compile_code, Interpreter},
+};
+use zk::{
+    circuit::BrainfuckCircuit,
+    diagnostics::{Diagnostics, SourceMap},
+    params::BrainfuckParams,
+    sizing::{min_k, SizeError, MAX_K},
+};
+
+/// Steps a fuzzed program may take before it is abandoned.
+pub const STEP_BUDGET: usize = 256;
+
+#[derive(Arbitrary, Debug)]
+pub struct Case {
+    /// Brainfuck code, as instruction bytes
+    pub code: Vec<u8>,
+    pub input: Vec<u8>,
+    /// selects the steps packed per circuit row, and whether the program is
+    /// public
+    pub packing: u8,
+}
+
+fn params(case: &Case) -> BrainfuckParams {
+    BrainfuckParams {
+        cell_bits: Some(8),
+        tape_size: 8,
+        max_program_len: 64,
+        max_steps: STEP_BUDGET,
+        steps_per_row: 1 + case.packing as usize % 3,
+        expose_program: case.packing / 3 % 2 == 1,
+        ..BrainfuckParams::default()
+    }
+}
+
+fn input(case: &Case) -> Vec<Fr> {
+    case.input
+        .iter()
+        .map(|&value| Fr::from(value as u64))
+        .collect()
+}
+
+/// Compiles and runs arbitrary code. Errors are expected; panics are not,
+/// and neither are traces that break the interpreter's own invariants.
+pub fn interpret(case: &Case) {
+    let Ok(program) = compile_code(&case.code) else {
+        return;
+    };
+    let config = VmConfig {
+        tape_size: 8,
+        cell_bits: Some(8),
+    };
+    let Ok(mut interpreter) = Interpreter::try_with_config(case.code.clone(), input(case), config)
+    else {
+        panic!("code compiles on its own but not in the interpreter");
+    };
+    assert_eq!(interpreter.program(), program);
+    if interpreter.try_run(STEP_BUDGET).is_err() {
+        return;
+    }
+    let tables = &interpreter.tables;
+    let halt = tables.processor_table.last().expect("a halt row");
+    assert_eq!(halt.ip, program.len());
+    assert_eq!(halt.clk as usize + 1, tables.processor_table.len());
+    assert_eq!(tables.memory_table.len(), tables.processor_table.len());
+    assert_eq!(tables.instruction_table.len(), program.len() + 1);
+}
+
+/// Proves every trace the interpreter produces within the circuit's
+/// parameters with the MockProver, at the size `min_k` picks.
+pub fn check_circuit(case: &Case) {
+    let params = params(case);
+    let Ok(mut interpreter) =
+        Interpreter::try_with_config(case.code.clone(), input(case), params.vm_config())
+    else {
+        return;
+    };
+    if interpreter.try_run(params.max_steps).is_err() {
+        return;
+    }
+    let tables = interpreter.tables;
+
+    let k = match min_k(&tables, &params, MAX_K) {
+        Ok(k) => k,
+        Err(SizeError::ProgramTooLong { .. }) => return,
+        Err(error) => panic!("a run within the step budget fits: {error}"),
+    };
+    let circuit = BrainfuckCircuit::new(tables.clone(), params);
+    let prover =
+        MockProver::run(k, &circuit, circuit.instances()).expect("witness assignment succeeds");
+    let source = SourceMap::new(&String::from_utf8_lossy(&case.code));
+    Diagnostics::new(&tables, &params, &source).assert_satisfied(&prover);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use arbitrary::Unstructured;
+
+    use super::*;
+
+    /// Runs `check` on every saved input of `target`.
+    fn replay(target: &str, check: fn(&Case)) {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("regressions")
+            .join(target);
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.file_name().is_some_and(|name| name == ".gitkeep") {
+                continue;
+            }
+            let data = fs::read(&path).unwrap();
+            let case = Case::arbitrary_take_rest(Unstructured::new(&data)).unwrap();
+            check(&case);
+        }
+    }
+
+    #[test]
+    fn test_interpreter_regressions() {
+        replay("interpreter", interpret);
+    }
+
+    #[test]
+    fn test_circuit_regressions() {
+        replay("circuit", check_circuit);
+    }
+}
@@ -1,113 +1,150 @@
+use std::sync::Arc;
+
 use halo2_proofs::{
-    arithmetic::Field,
     circuit::SimpleFloorPlanner,
-    halo2curves::bn256::Fr,
-    plonk::{Advice, Circuit, Column, ConstraintSystem, Expression, Selector},
-    poly::Rotation,
-};
-use vm::{
-    interpreter::{ADD, GETCHAR, LB, PUTCHAR, RB, SHL, SHR, SUB},
-    table::Tables,
+    halo2curves::{bn256::Fr, ff::PrimeField},
+    plonk::{Circuit, Column, ConstraintSystem, Instance},
 };
+use vm::table::Tables;
 
+#[cfg(test)]
+use crate::table::Forged;
 use crate::{
-    input_table::{InputTableChip, InputTableConfig},
-    instruction_table::{self, InstructionTableChip, InstructionTableConfig},
-    memory_table::{self, MemoryTableChip, MemoryTableConfig},
-    output_table::{OutputTableChip, OutputTableConfig},
-    processor_table::{self, ProcessTableChip, ProcessorTableConfig},
+    gadgets::range_check::{RangeCheckChip, RangeCheckConfig},
+    instruction_table::InstructionTable,
+    io_table::{InputTable, OutputTable},
+    memory_table::MemoryTable,
+    params::BrainfuckParams,
+    processor_table::ProcessorTable,
+    public_inputs::PublicInputs,
+    table::{Composer, ConfiguredTable, PublicCell, TableContext, Witness},
 };
 #[derive(Clone)]
-struct BrainfuckConfig {
-    processor_table: ProcessorTableConfig,
-    memory_table: MemoryTableConfig,
-    instruction_table: InstructionTableConfig,
-    input_table: InputTableConfig,
-    output_table: OutputTableConfig,
+pub struct BrainfuckConfig<F: PrimeField> {
+    /// configured tables, in the order they are assigned
+    pub(crate) tables: Vec<Arc<dyn ConfiguredTable<F>>>,
+    summary: Column<Instance>,
+    range_check: RangeCheckConfig,
+}
+impl<F: PrimeField> BrainfuckConfig<F> {
+    /// Rows taken by the tallest of the lookup tables loaded at synthesis.
+    pub(crate) fn lookup_rows(&self) -> usize {
+        self.tables
+            .iter()
+            .flat_map(|table| table.fixed_tables())
+            .map(|fixed| fixed.values.len())
+            .fold(self.range_check.size(), usize::max)
+    }
 }
+/// Row of the `summary` instance column holding the number of inputs.
+pub const INPUT_LEN_ROW: usize = 0;
+/// Row of the `summary` instance column holding the number of outputs.
+pub const OUTPUT_LEN_ROW: usize = 1;
+/// Row of the `summary` instance column holding the program length, which is
+/// the ip the program must halt at.
+pub const PROGRAM_LEN_ROW: usize = 2;
+/// Row of the `summary` instance column holding the clk of the halt row, when
+/// `BrainfuckParams::expose_final_clk` is set.
+pub const FINAL_CLK_ROW: usize = 3;
+/// The Brainfuck circuit over the scalar field `F` of the proving system's
+/// curve, `Fr` for KZG over bn256.
 #[derive(Default)]
-struct BrainfuckCircuit {
-    tables: Tables,
+pub struct BrainfuckCircuit<F = Fr> {
+    tables: Tables<F>,
+    params: BrainfuckParams,
+    #[cfg(test)]
+    pub(crate) forged: Forged<F>,
+}
+impl<F: PrimeField> BrainfuckCircuit<F> {
+    /// `tables` must come from an interpreter run with `params.vm_config()`.
+    pub fn new(tables: Tables<F>, params: BrainfuckParams) -> Self {
+        Self {
+            tables,
+            params,
+            #[cfg(test)]
+            forged: Forged::default(),
+        }
+    }
+
+    /// What a proof of the run claims.
+    pub fn public_inputs(&self) -> PublicInputs<F> {
+        PublicInputs::from_tables(&self.tables, &self.params)
+    }
+
+    /// Instance columns the run is proven against.
+    pub fn instances(&self) -> Vec<Vec<F>> {
+        self.public_inputs().to_instances(&self.params)
+    }
 }
-impl Circuit<Fr> for BrainfuckCircuit {
-    type Config = BrainfuckConfig;
+impl<F: PrimeField> Circuit<F> for BrainfuckCircuit<F> {
+    type Config = BrainfuckConfig<F>;
     type FloorPlanner = SimpleFloorPlanner;
+    type Params = BrainfuckParams;
     fn without_witnesses(&self) -> Self {
-        Self::default()
+        Self {
+            tables: Tables::default(),
+            params: self.params,
+            #[cfg(test)]
+            forged: Forged::default(),
+        }
+    }
+    fn params(&self) -> Self::Params {
+        self.params
     }
-    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
-        let processor_table = ProcessTableChip::configure(meta);
-        let memory_table = MemoryTableChip::configure(meta);
-        let instruction_table = InstructionTableChip::configure(meta);
-        let input_table = InputTableChip::configure(meta);
-        let output_table = OutputTableChip::configure(meta);
-
-        meta.lookup_any("memory table permutation constraints", |meta| {
-            let processor_clk = meta.query_advice(processor_table.clk, Rotation::cur());
-            let processor_mp = meta.query_advice(processor_table.mp, Rotation::cur());
-            let processor_mv = meta.query_advice(processor_table.mv, Rotation::cur());
-            let memory_clk = meta.query_advice(memory_table.clk, Rotation::cur());
-            let memory_mp = meta.query_advice(memory_table.mp, Rotation::cur());
-            let memory_mv = meta.query_advice(memory_table.mv, Rotation::cur());
-            vec![
-                (memory_clk, processor_clk),
-                (memory_mp, processor_mp),
-                (memory_mv, processor_mv),
-            ]
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        Self::configure_with_params(meta, BrainfuckParams::default())
+    }
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        params.validate();
+        let range_check = RangeCheckChip::configure(meta, params.range_bits());
+        let mut composer = Composer::new(TableContext {
+            range_check: &range_check,
+            params: &params,
         });
+        composer.add::<ProcessorTable>(meta);
+        composer.add::<MemoryTable>(meta);
+        composer.add::<InstructionTable>(meta);
+        composer.add::<InputTable>(meta);
+        composer.add::<OutputTable>(meta);
+        let tables = composer.finish(meta);
 
-        meta.lookup_any("instruction table permutation constraints", |meta| {
-            let processor_ip = meta.query_advice(processor_table.ip, Rotation::cur());
-            let processor_ci = meta.query_advice(processor_table.ci, Rotation::cur());
-            let processor_ni = meta.query_advice(processor_table.ni, Rotation::cur());
-            let instruction_ip = meta.query_advice(instruction_table.ip, Rotation::cur());
-            let instruction_ci = meta.query_advice(instruction_table.ci, Rotation::cur());
-            let instruction_ni = meta.query_advice(instruction_table.ni, Rotation::cur());
-            vec![
-                (instruction_ip, processor_ip),
-                (instruction_ci, processor_ci),
-                (instruction_ni, processor_ni),
-            ]
-        });
-        meta.lookup_any("permutation constraint: input table", |meta| {
-            let processor_clk = meta.query_advice(processor_table.clk, Rotation::cur());
-            let processor_mv = meta.query_advice(processor_table.mv, Rotation::cur());
-            let input_clk = meta.query_advice(input_table.clk, Rotation::cur());
-            let input_value = meta.query_instance(input_table.value, Rotation::cur());
-            vec![(input_clk, processor_clk), (input_value, processor_mv)]
-        });
-        meta.lookup_any("permutation constraint: output table", |meta| {
-            let processor_clk = meta.query_advice(processor_table.clk, Rotation::cur());
-            let processor_mv = meta.query_advice(processor_table.mv, Rotation::cur());
-            let output_clk = meta.query_advice(output_table.clk, Rotation::cur());
-            let output_value = meta.query_instance(output_table.value, Rotation::cur());
-            vec![(output_clk, processor_clk), (output_value, processor_mv)]
-        });
+        let summary = meta.instance_column();
+        meta.enable_equality(summary);
+        meta.annotate_lookup_any_column(summary, || "summary");
 
         Self::Config {
-            processor_table,
-            memory_table,
-            instruction_table,
-            input_table,
-            output_table,
+            tables,
+            summary,
+            range_check,
         }
     }
     fn synthesize(
         &self,
         config: Self::Config,
-        mut layouter: impl halo2_proofs::circuit::Layouter<Fr>,
+        mut layouter: impl halo2_proofs::circuit::Layouter<F>,
     ) -> Result<(), halo2_proofs::plonk::ErrorFront> {
-        let processor_chip = ProcessTableChip::construct(config.processor_table);
-        let memory_chip = MemoryTableChip::construct(config.memory_table);
-        let instruction_chip = InstructionTableChip::construct(config.instruction_table);
-        let input_chip = InputTableChip::construct(config.input_table);
-        let output_chip = OutputTableChip::construct(config.output_table);
-
-        processor_chip.assign(layouter.namespace(|| "processor table"), &self.tables)?;
-        memory_chip.assign(layouter.namespace(|| "memory table"), &self.tables)?;
-        instruction_chip.assign(layouter.namespace(|| "instruction table"), &self.tables)?;
-        input_chip.assign(layouter.namespace(|| "input table"), &self.tables)?;
-        output_chip.assign(layouter.namespace(|| "output table"), &self.tables)?;
+        RangeCheckChip::construct(config.range_check).load(&mut layouter)?;
+        for table in &config.tables {
+            for fixed in table.fixed_tables() {
+                fixed.load(&mut layouter)?;
+            }
+        }
+
+        let height = self.params.height();
+        let witness = Witness {
+            tables: &self.tables,
+            #[cfg(test)]
+            forged: &self.forged,
+        };
+        for table in &config.tables {
+            let public = layouter.assign_region(
+                || format!("{} table", table.name()),
+                |mut region| table.assign(&mut region, &witness, height),
+            )?;
+            for PublicCell { row, cell } in public {
+                layouter.constrain_instance(cell.cell(), config.summary, row)?;
+            }
+        }
 
         Ok(())
     }
@@ -116,36 +153,104 @@ impl Circuit<Fr> for BrainfuckCircuit {
 #[cfg(test)]
 mod tests {
 
-    use halo2_proofs::dev::MockProver;
-    use vm::interpreter::Interpreter;
+    use halo2_proofs::{
+        dev::MockProver,
+        halo2curves::{ff::FromUniformBytes, pasta::Fp},
+    };
+    use vm::interpreter::{Interpreter, ADD, SHL, SHR, SUB};
 
     use super::*;
+    use crate::{
+        fixtures::{self, CODE},
+        params::IoMode,
+        sizing::{min_k, MAX_K},
+    };
 
     #[test]
     fn test_run() {
-        let code = vec![
-            ADD, ADD, SHR, GETCHAR, SHL, LB, SHR, ADD, PUTCHAR, SHL, SUB, RB,
-        ];
-        let input = vec![Fr::from(97)];
-        let mut interpreter = Interpreter::new(code, input);
+        let params = BrainfuckParams {
+            expose_final_clk: true,
+            ..fixtures::params()
+        };
+        let mut interpreter =
+            Interpreter::with_config(CODE.to_vec(), vec![Fr::from(97)], params.vm_config());
         interpreter.run();
+        let program_len = interpreter.program().len();
         let tables = interpreter.tables;
-        let circuit = BrainfuckCircuit {
-            tables: tables.clone(),
+        let public = PublicInputs {
+            program: None,
+            program_len: program_len as u64,
+            inputs: Some(vec![Fr::from(97)]),
+            input_len: 1,
+            outputs: vec![Fr::from(98), Fr::from(99)],
+            final_clk: Some(tables.processor_table.last().unwrap().clk),
         };
-        let input_val = tables
-            .clone()
-            .input_table
-            .iter()
-            .map(|v| v.value)
-            .collect::<Vec<Fr>>();
-        let output_val = tables
-            .clone()
-            .output_table
-            .iter()
-            .map(|v| v.value)
-            .collect::<Vec<Fr>>();
-        let prover = MockProver::run(9, &circuit, vec![output_val, input_val]).unwrap();
+        let k = min_k(&tables, &params, MAX_K).unwrap();
+        let circuit = BrainfuckCircuit::new(tables, params);
+        assert_eq!(circuit.public_inputs(), public);
+        let prover = MockProver::run(k, &circuit, public.to_instances(&params)).unwrap();
+        prover.assert_satisfied();
+
+        let mut other = public;
+        other.outputs[1] = Fr::from(100);
+        let prover = MockProver::run(k, &circuit, other.to_instances(&params)).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    /// Runs the example program at 2 and 3 steps per row, so that the halt
+    /// row lands in a middle slot as well as in the last one, with the
+    /// program public.
+    fn packed_steps<F: PrimeField + FromUniformBytes<64> + Ord>() {
+        for steps_per_row in [2, 3] {
+            let params = BrainfuckParams {
+                steps_per_row,
+                expose_program: true,
+                ..fixtures::params()
+            };
+            let mut interpreter =
+                Interpreter::with_config(CODE.to_vec(), vec![F::from(97)], params.vm_config());
+            interpreter.run();
+            let program = interpreter.program().to_vec();
+            let tables = interpreter.tables;
+            let k = min_k(&tables, &params, MAX_K).unwrap();
+            let circuit = BrainfuckCircuit::new(tables, params);
+            let public = circuit.public_inputs();
+            assert_eq!(public.program.as_ref(), Some(&program));
+            let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+            prover.assert_satisfied();
+
+            // another program of the same length
+            let mut other = public;
+            other.program.as_mut().unwrap()[0] = SUB;
+            let prover = MockProver::run(k, &circuit, other.to_instances(&params)).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    #[test]
+    fn test_packed_steps() {
+        packed_steps::<Fr>();
+        packed_steps::<Fp>();
+    }
+
+    #[test]
+    fn test_wrapping_cells_without_io() {
+        let code = vec![SUB, SHR, ADD, SHL, ADD];
+        let params = BrainfuckParams {
+            cell_bits: Some(8),
+            tape_size: 4,
+            max_program_len: 8,
+            max_steps: 8,
+            io_mode: IoMode::None,
+            ..BrainfuckParams::default()
+        };
+        let mut interpreter = Interpreter::with_config(code, vec![], params.vm_config());
+        interpreter.run();
+        let k = min_k(&interpreter.tables, &params, MAX_K).unwrap();
+        let circuit = BrainfuckCircuit::new(interpreter.tables, params);
+        let instances = circuit.instances();
+        assert_eq!(instances, vec![vec![Fr::ZERO, Fr::ZERO, Fr::from(5)]]);
+        let prover = MockProver::run(k, &circuit, instances).unwrap();
         prover.assert_satisfied();
     }
 }
@@ -0,0 +1,359 @@
+use halo2_proofs::{
+    arithmetic::Field,
+    dev::{FailureLocation, MockProver, VerifyFailure},
+    halo2curves::{
+        bn256::Fr,
+        ff::{FromUniformBytes, PrimeField},
+    },
+};
+use vm::{
+    interpreter::{ADD, GETCHAR, LB, RB, SHL, SHR, SUB},
+    table::{ProcessTableRow, Tables},
+};
+
+use crate::params::BrainfuckParams;
+
+/// Position of an instruction in the Brainfuck source, lines and columns
+/// counted from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourcePos {
+    pub op: char,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Maps positions of the compiled program back to the Brainfuck source.
+/// Every character but the eight instructions is a comment.
+pub struct SourceMap {
+    /// source position of each program position, `None` for jump targets
+    positions: Vec<Option<SourcePos>>,
+}
+impl SourceMap {
+    pub fn new(source: &str) -> Self {
+        let mut positions = vec![];
+        for (line, text) in source.lines().enumerate() {
+            for (col, op) in text.chars().enumerate() {
+                if !"<>+-,.[]".contains(op) {
+                    continue;
+                }
+                positions.push(Some(SourcePos {
+                    op,
+                    line: line + 1,
+                    col: col + 1,
+                }));
+                // the compiler writes a jump target after each bracket
+                if op == '[' || op == ']' {
+                    positions.push(None);
+                }
+            }
+        }
+        Self { positions }
+    }
+
+    /// The instructions of the source, as the code to hand to `Interpreter`.
+    pub fn code(&self) -> Vec<u8> {
+        self.positions
+            .iter()
+            .flatten()
+            .map(|pos| pos.op as u8)
+            .collect()
+    }
+
+    /// Source position of the instruction at `ip`, or of the bracket owning
+    /// the jump target at `ip`. `None` past the end of the program.
+    pub fn locate(&self, ip: usize) -> Option<SourcePos> {
+        match self.positions.get(ip)? {
+            Some(pos) => Some(*pos),
+            None => self.positions[ip - 1],
+        }
+    }
+}
+
+/// Turns MockProver failures into messages about the steps of the program
+/// that caused them, such as
+/// ``step clk=42 (`]` at line 3 col 7): ip transition violated, expected 12 got 9``.
+pub struct Diagnostics<'a, F = Fr> {
+    tables: &'a Tables<F>,
+    params: &'a BrainfuckParams,
+    source: &'a SourceMap,
+}
+impl<'a, F: PrimeField> Diagnostics<'a, F> {
+    /// `tables` and `params` must be those the circuit was built from, and
+    /// `source` the program the trace was taken from.
+    pub fn new(tables: &'a Tables<F>, params: &'a BrainfuckParams, source: &'a SourceMap) -> Self {
+        Self {
+            tables,
+            params,
+            source,
+        }
+    }
+
+    /// Like `MockProver::assert_satisfied`, with the failures explained.
+    pub fn assert_satisfied(&self, prover: &MockProver<F>)
+    where
+        F: FromUniformBytes<64> + Ord,
+    {
+        if let Err(failures) = prover.verify() {
+            let mut messages = failures
+                .iter()
+                .map(|failure| self.explain(failure))
+                .collect::<Vec<_>>();
+            // the constraints of a gate fail together
+            messages.dedup();
+            panic!("circuit is not satisfied:\n{}", messages.join("\n"));
+        }
+    }
+
+    pub fn explain(&self, failure: &VerifyFailure) -> String {
+        match failure {
+            VerifyFailure::ConstraintNotSatisfied {
+                constraint,
+                location,
+                ..
+            } => {
+                let constraint = constraint.to_string();
+                let gate = last_quoted(&constraint).unwrap_or(&constraint);
+                let Some((table, rows)) = self.rows_at(location, None) else {
+                    return format!("{location}: {gate} violated");
+                };
+                let steps = self.steps(table, &rows);
+                let transition = (gate.contains("processor") && gate.contains("transition"))
+                    .then(|| steps.iter().find_map(|&clk| self.transition_error(clk)))
+                    .flatten();
+                match transition {
+                    Some((clk, register, expected, actual)) => format!(
+                        "{}: {register} transition violated, expected {expected} got {actual}",
+                        self.step_label(clk)
+                    ),
+                    None => format!("{}: {gate} violated", self.label(table, &rows, &steps)),
+                }
+            }
+            VerifyFailure::Lookup { name, location, .. } => {
+                match self.rows_at(location, input_slot(name)) {
+                    Some((table, rows)) => format!(
+                        "{}: lookup {name} failed",
+                        self.label(table, &rows, &self.steps(table, &rows))
+                    ),
+                    None => format!("{location}: lookup {name} failed"),
+                }
+            }
+            VerifyFailure::Permutation { column, location } => match location {
+                FailureLocation::OutsideRegion { row } => {
+                    format!("public input row {row} of {column} does not match its cell")
+                }
+                _ => match self.rows_at(location, None) {
+                    Some((table, rows)) => format!(
+                        "{}: copy of {column} does not match the public input",
+                        self.label(table, &rows, &self.steps(table, &rows))
+                    ),
+                    None => failure.to_string(),
+                },
+            },
+            _ => failure.to_string(),
+        }
+    }
+
+    /// Table name and logical rows of a failure location: the row in
+    /// `slot`, or every row of the physical row when the slot is unknown.
+    fn rows_at(
+        &self,
+        location: &FailureLocation,
+        slot: Option<usize>,
+    ) -> Option<(&'static str, Vec<usize>)> {
+        let FailureLocation::InRegion { region, offset } = location else {
+            return None;
+        };
+        let region = region.to_string();
+        let name = last_quoted(&region)?.strip_suffix(" table")?;
+        let table = ["processor", "memory", "instruction", "input", "output"]
+            .into_iter()
+            .find(|&table| table == name)?;
+        let slots = self.params.steps_per_row;
+        let rows = match slot {
+            Some(slot) => vec![offset * slots + slot],
+            None => (offset * slots..(offset + 1) * slots).collect(),
+        };
+        Some((table, rows))
+    }
+
+    /// Clks of the steps behind logical rows of `table`. Padding rows have
+    /// none.
+    fn steps(&self, table: &str, rows: &[usize]) -> Vec<u64> {
+        let tables = self.tables;
+        rows.iter()
+            .filter_map(|&row| match table {
+                "processor" => tables.processor_table.get(row).map(|row| row.clk),
+                "memory" => tables.memory_table.get(row).map(|row| row.clk),
+                "input" => tables.input_table.get(row).map(|row| row.clk),
+                "output" => tables.output_table.get(row).map(|row| row.clk),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn label(&self, table: &str, rows: &[usize], steps: &[u64]) -> String {
+        if !steps.is_empty() {
+            return steps
+                .iter()
+                .map(|&clk| self.step_label(clk))
+                .collect::<Vec<_>>()
+                .join(", ");
+        }
+        let program = &self.tables.instruction_table;
+        match rows.iter().find_map(|&row| program.get(row)) {
+            Some(row) if table == "instruction" => match self.source.locate(row.ip) {
+                Some(pos) => format!("program ip={} ({})", row.ip, describe(pos)),
+                None => format!("program ip={} (halt)", row.ip),
+            },
+            _ => format!("{table} table padding row {}", rows[0]),
+        }
+    }
+
+    fn step_label(&self, clk: u64) -> String {
+        match self.step(clk) {
+            Some(row) => match self.source.locate(row.ip) {
+                Some(pos) => format!("step clk={clk} ({})", describe(pos)),
+                None => format!("step clk={clk} (halt)"),
+            },
+            None => format!("step clk={clk}"),
+        }
+    }
+
+    fn step(&self, clk: u64) -> Option<&ProcessTableRow<F>> {
+        self.tables.processor_table.get(clk as usize)
+    }
+
+    /// The first register of the step after `clk` that does not follow from
+    /// executing step `clk`, with its expected and actual values.
+    fn transition_error(&self, clk: u64) -> Option<(u64, &'static str, String, String)> {
+        let cur = self.step(clk)?;
+        let next = self.step(clk + 1)?;
+        if cur.ci == 0 {
+            return None;
+        }
+        let mismatch = |register, expected: String, actual: String| {
+            (expected != actual).then(|| (clk, register, expected, actual))
+        };
+        let ip = match cur.ci {
+            LB if cur.mv == F::ZERO => cur.ni as usize,
+            RB if cur.mv != F::ZERO => cur.ni as usize,
+            LB | RB => cur.ip + 2,
+            _ => cur.ip + 1,
+        };
+        let mp = match cur.ci {
+            SHL => cur.mp as i64 - 1,
+            SHR => cur.mp as i64 + 1,
+            _ => cur.mp as i64,
+        };
+        let modulus = self
+            .params
+            .cell_bits
+            .map(|bits| F::from(2).pow_vartime([bits as u64]));
+        let mv = match (cur.ci, modulus) {
+            (ADD, Some(modulus)) if cur.mv + F::ONE == modulus => Some(F::ZERO),
+            (ADD, _) => Some(cur.mv + F::ONE),
+            (SUB, Some(modulus)) if cur.mv == F::ZERO => Some(modulus - F::ONE),
+            (SUB, _) => Some(cur.mv - F::ONE),
+            // the new value comes from another cell or the input
+            (SHL | SHR | GETCHAR, _) => None,
+            _ => Some(cur.mv),
+        };
+        mismatch("clk", (clk + 1).to_string(), next.clk.to_string())
+            .or_else(|| mismatch("ip", ip.to_string(), next.ip.to_string()))
+            .or_else(|| mismatch("mp", mp.to_string(), next.mp.to_string()))
+            .or_else(|| mv.and_then(|mv| mismatch("mv", show(mv), show(next.mv))))
+    }
+}
+
+fn describe(pos: SourcePos) -> String {
+    format!("`{}` at line {} col {}", pos.op, pos.line, pos.col)
+}
+
+/// Small field elements and their negations in decimal.
+fn show<F: PrimeField>(value: F) -> String {
+    let small = |value: F| {
+        let repr = value.to_repr();
+        let (low, high) = repr.as_ref().split_at(8);
+        high.iter()
+            .all(|&byte| byte == 0)
+            .then(|| u64::from_le_bytes(low.try_into().unwrap()))
+    };
+    match (small(value), small(-value)) {
+        (Some(value), _) => value.to_string(),
+        (_, Some(negated)) => format!("-{negated}"),
+        _ => format!("{value:?}"),
+    }
+}
+
+/// The last `('...')` of a halo2 metadata display, which names the region,
+/// gate or column.
+fn last_quoted(text: &str) -> Option<&str> {
+    let start = text.rfind("('")? + 2;
+    let end = start + text[start..].find("')")?;
+    Some(&text[start..end])
+}
+
+/// Input slot of a lookup named by `Composer::finish` after a packed table,
+/// `"<argument> (slot <input> in <table>)"`.
+fn input_slot(name: &str) -> Option<usize> {
+    let (_, slots) = name.rsplit_once(" (slot ")?;
+    slots.split_once(" in ")?.0.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use vm::interpreter::Interpreter;
+
+    use super::*;
+    use crate::{
+        circuit::BrainfuckCircuit,
+        fixtures,
+        sizing::{min_k, MAX_K},
+    };
+
+    #[test]
+    fn test_source_map() {
+        let source = SourceMap::new("++ add two\n[->+<]");
+        assert_eq!(source.code(), b"++[->+<]");
+        assert_eq!(
+            source.locate(3),
+            Some(SourcePos {
+                op: '[',
+                line: 2,
+                col: 1
+            })
+        );
+        assert_eq!(source.locate(4).unwrap().op, '-');
+        assert_eq!(source.locate(10), None);
+    }
+
+    #[test]
+    fn test_tampered_ip_explained() {
+        let source = SourceMap::new("++\n>,<[>+.<-]");
+        let params = fixtures::params();
+        let mut interpreter =
+            Interpreter::with_config(source.code(), vec![Fr::from(97)], params.vm_config());
+        interpreter.run();
+        let mut tables = interpreter.tables;
+        // step 2 is the `>` at ip 2, so step 3 should be at ip 3
+        tables.processor_table[3].ip = 7;
+
+        let k = min_k(&tables, &params, MAX_K).unwrap();
+        let circuit = BrainfuckCircuit::new(tables.clone(), params);
+        let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+        let diagnostics = Diagnostics::new(&tables, &params, &source);
+        let messages = prover
+            .verify()
+            .unwrap_err()
+            .iter()
+            .map(|failure| diagnostics.explain(failure))
+            .collect::<Vec<_>>();
+        assert!(
+            messages.contains(
+                &"step clk=2 (`>` at line 2 col 1): ip transition violated, expected 3 got 7"
+                    .to_string()
+            ),
+            "{messages:#?}"
+        );
+    }
+}
@@ -0,0 +1,614 @@
+//! Versioned on-disk formats for proofs, keys and instance columns.
+//!
+//! Every envelope starts with a 4-byte magic naming its kind and the format
+//! version. Keys and proofs carry the fingerprint of the circuit
+//! configuration they belong to, so that mixing artifacts of different
+//! configurations fails on load rather than as an unexplained verification
+//! failure. Integers are little-endian and field elements are stored as
+//! their canonical representation.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use halo2_proofs::{
+    halo2curves::ff::PrimeField,
+    plonk::{pk_read, vk_read, ProvingKey, VerifyingKey},
+    SerdeFormat,
+};
+use vm::table::Tables;
+
+use crate::{
+    circuit::BrainfuckCircuit,
+    params::{BrainfuckParams, IoMode},
+    prover::{Backend, Proof},
+    sizing::params_k,
+};
+
+/// Version of every envelope written by this crate.
+pub const FORMAT_VERSION: u16 = 1;
+
+const PROOF_MAGIC: [u8; 4] = *b"BFPF";
+const VK_MAGIC: [u8; 4] = *b"BFVK";
+const PK_MAGIC: [u8; 4] = *b"BFPK";
+const INSTANCES_MAGIC: [u8; 4] = *b"BFIN";
+
+/// Keys are written the way `keygen_vk` makes them.
+const COMPRESS_SELECTORS: bool = true;
+
+/// Hash of everything the keys of a circuit depend on: the curve, the
+/// circuit parameters and the circuit size.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl Fingerprint {
+    pub fn new<B: Backend>(params: &BrainfuckParams, k: u32) -> Self {
+        let mut state = blake2b_simd::Params::new()
+            .hash_length(32)
+            .personal(b"brainfuck-config")
+            .to_state();
+        state.update(&(B::CURVE.len() as u32).to_le_bytes());
+        state.update(B::CURVE.as_bytes());
+        state.update(&params_bytes(params));
+        state.update(&k.to_le_bytes());
+        Self(state.finalize().as_bytes().try_into().unwrap())
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fingerprint({self})")
+    }
+}
+
+#[derive(Debug)]
+pub enum EnvelopeError {
+    Io(io::Error),
+    /// The data is not an envelope of the expected kind.
+    Magic {
+        kind: &'static str,
+        found: [u8; 4],
+    },
+    /// The envelope was written by another version of the format.
+    Version {
+        kind: &'static str,
+        found: u16,
+        supported: u16,
+    },
+    /// The proof was made with another commitment scheme, or the key is over
+    /// another curve.
+    Backend {
+        expected: String,
+        found: String,
+    },
+    /// The envelope belongs to another circuit configuration.
+    ConfigMismatch {
+        expected: Fingerprint,
+        found: Fingerprint,
+    },
+    /// The envelope is truncated or holds invalid values.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::Io(error) => write!(f, "{error}"),
+            EnvelopeError::Magic { kind, found } => {
+                write!(f, "not a {kind} envelope (magic {found:?})")
+            }
+            EnvelopeError::Version {
+                kind,
+                found,
+                supported,
+            } => write!(
+                f,
+                "{kind} envelope has format version {found}, only {supported} is supported"
+            ),
+            EnvelopeError::Backend { expected, found } => {
+                write!(f, "envelope is for {found}, expected {expected}")
+            }
+            EnvelopeError::ConfigMismatch { expected, found } => write!(
+                f,
+                "envelope is for circuit configuration {found}, expected {expected}"
+            ),
+            EnvelopeError::Malformed(what) => write!(f, "malformed envelope: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+impl From<io::Error> for EnvelopeError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => EnvelopeError::Malformed("unexpected end of data"),
+            _ => EnvelopeError::Io(error),
+        }
+    }
+}
+
+/// A key loaded from an envelope, with the configuration it was made for.
+pub struct StoredKey<K> {
+    pub params: BrainfuckParams,
+    pub k: u32,
+    pub fingerprint: Fingerprint,
+    pub key: K,
+}
+
+/// Writes `proof`, made with `backend` for the configuration `fingerprint`.
+pub fn write_proof<B: Backend>(
+    writer: &mut impl Write,
+    backend: &B,
+    fingerprint: &Fingerprint,
+    proof: &Proof<B::Scalar>,
+) -> Result<(), EnvelopeError> {
+    write_header(writer, PROOF_MAGIC)?;
+    write_str(writer, backend.name())?;
+    writer.write_all(&fingerprint.0)?;
+    write_columns(writer, &proof.instances)?;
+    write_bytes(writer, &proof.bytes)?;
+    Ok(())
+}
+
+/// Reads a proof, checking it was made with `backend` for the configuration
+/// `expected`, typically the fingerprint of the verifying key.
+pub fn read_proof<B: Backend>(
+    reader: &mut impl Read,
+    backend: &B,
+    expected: &Fingerprint,
+) -> Result<Proof<B::Scalar>, EnvelopeError> {
+    read_header(reader, PROOF_MAGIC, "proof")?;
+    check_backend(backend.name(), read_str(reader)?)?;
+    check_fingerprint(expected, read_fingerprint(reader)?)?;
+    let instances = read_columns(reader)?;
+    let bytes = read_bytes(reader)?;
+    Ok(Proof { instances, bytes })
+}
+
+/// Writes instance columns on their own, for verifiers that receive the
+/// public inputs separately from the proof.
+pub fn write_instances<F: PrimeField>(
+    writer: &mut impl Write,
+    fingerprint: &Fingerprint,
+    instances: &[Vec<F>],
+) -> Result<(), EnvelopeError> {
+    write_header(writer, INSTANCES_MAGIC)?;
+    writer.write_all(&fingerprint.0)?;
+    write_columns(writer, instances)
+}
+
+pub fn read_instances<F: PrimeField>(
+    reader: &mut impl Read,
+    expected: &Fingerprint,
+) -> Result<Vec<Vec<F>>, EnvelopeError> {
+    read_header(reader, INSTANCES_MAGIC, "instances")?;
+    check_fingerprint(expected, read_fingerprint(reader)?)?;
+    read_columns(reader)
+}
+
+/// Writes the verifying key of circuits with `params`, generated for `2^k`
+/// rows, along with the parameters themselves.
+pub fn write_vk<B: Backend>(
+    writer: &mut impl Write,
+    params: &BrainfuckParams,
+    k: u32,
+    vk: &VerifyingKey<B::Curve>,
+) -> Result<(), EnvelopeError> {
+    write_key_header::<B>(writer, VK_MAGIC, params, k)?;
+    vk.write(writer, SerdeFormat::RawBytes)?;
+    Ok(())
+}
+
+/// Reads a verifying key. The circuit parameters come from the envelope, so
+/// the caller needs neither them nor a trace.
+pub fn read_vk<B: Backend>(
+    reader: &mut impl Read,
+) -> Result<StoredKey<VerifyingKey<B::Curve>>, EnvelopeError> {
+    let (params, k, fingerprint) = read_key_header::<B>(reader, VK_MAGIC, "verifying key")?;
+    let circuit = BrainfuckCircuit::<B::Scalar>::new(Tables::default(), params);
+    let key = vk_read(
+        reader,
+        SerdeFormat::RawBytes,
+        k,
+        &circuit,
+        COMPRESS_SELECTORS,
+    )?;
+    Ok(StoredKey {
+        params,
+        k,
+        fingerprint,
+        key,
+    })
+}
+
+pub fn write_pk<B: Backend>(
+    writer: &mut impl Write,
+    params: &BrainfuckParams,
+    k: u32,
+    pk: &ProvingKey<B::Curve>,
+) -> Result<(), EnvelopeError> {
+    write_key_header::<B>(writer, PK_MAGIC, params, k)?;
+    pk.write(writer, SerdeFormat::RawBytes)?;
+    Ok(())
+}
+
+pub fn read_pk<B: Backend>(
+    reader: &mut impl Read,
+) -> Result<StoredKey<ProvingKey<B::Curve>>, EnvelopeError> {
+    let (params, k, fingerprint) = read_key_header::<B>(reader, PK_MAGIC, "proving key")?;
+    let circuit = BrainfuckCircuit::<B::Scalar>::new(Tables::default(), params);
+    let key = pk_read(
+        reader,
+        SerdeFormat::RawBytes,
+        k,
+        &circuit,
+        COMPRESS_SELECTORS,
+    )?;
+    Ok(StoredKey {
+        params,
+        k,
+        fingerprint,
+        key,
+    })
+}
+
+fn write_header(writer: &mut impl Write, magic: [u8; 4]) -> io::Result<()> {
+    writer.write_all(&magic)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())
+}
+
+fn read_header(
+    reader: &mut impl Read,
+    magic: [u8; 4],
+    kind: &'static str,
+) -> Result<(), EnvelopeError> {
+    let mut found = [0; 4];
+    reader.read_exact(&mut found)?;
+    if found != magic {
+        return Err(EnvelopeError::Magic { kind, found });
+    }
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version != FORMAT_VERSION {
+        return Err(EnvelopeError::Version {
+            kind,
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    Ok(())
+}
+
+/// Header of a key: the curve, the circuit parameters and their
+/// fingerprint, which guards the parameters against corruption.
+fn write_key_header<B: Backend>(
+    writer: &mut impl Write,
+    magic: [u8; 4],
+    params: &BrainfuckParams,
+    k: u32,
+) -> Result<(), EnvelopeError> {
+    write_header(writer, magic)?;
+    write_str(writer, B::CURVE)?;
+    writer.write_all(&params_bytes(params))?;
+    writer.write_all(&k.to_le_bytes())?;
+    writer.write_all(&Fingerprint::new::<B>(params, k).0)?;
+    Ok(())
+}
+
+fn read_key_header<B: Backend>(
+    reader: &mut impl Read,
+    magic: [u8; 4],
+    kind: &'static str,
+) -> Result<(BrainfuckParams, u32, Fingerprint), EnvelopeError> {
+    read_header(reader, magic, kind)?;
+    check_backend(B::CURVE, read_str(reader)?)?;
+    let mut bytes = [0; PARAMS_LEN];
+    reader.read_exact(&mut bytes)?;
+    let params = params_from_bytes(&bytes)?;
+    let k = read_u32(reader)?;
+    // keys are only ever generated at this size, see `prover::check_setup`,
+    // and reading them at another would build a circuit of any size
+    if Ok(k) != params_k(&params, B::Scalar::S) {
+        return Err(EnvelopeError::Malformed(
+            "key size does not fit its parameters",
+        ));
+    }
+    let fingerprint = read_fingerprint(reader)?;
+    check_fingerprint(&Fingerprint::new::<B>(&params, k), fingerprint)?;
+    Ok((params, k, fingerprint))
+}
+
+fn check_backend(expected: &str, found: String) -> Result<(), EnvelopeError> {
+    if found != expected {
+        return Err(EnvelopeError::Backend {
+            expected: expected.to_string(),
+            found,
+        });
+    }
+    Ok(())
+}
+
+fn check_fingerprint(expected: &Fingerprint, found: Fingerprint) -> Result<(), EnvelopeError> {
+    if found != *expected {
+        return Err(EnvelopeError::ConfigMismatch {
+            expected: *expected,
+            found,
+        });
+    }
+    Ok(())
+}
+
+fn read_fingerprint(reader: &mut impl Read) -> io::Result<Fingerprint> {
+    let mut bytes = [0; 32];
+    reader.read_exact(&mut bytes)?;
+    Ok(Fingerprint(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Reads a length-prefixed byte string without trusting the length for the
+/// allocation.
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>, EnvelopeError> {
+    let len = read_u32(reader)? as usize;
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(EnvelopeError::Malformed("unexpected end of data"));
+    }
+    Ok(bytes)
+}
+
+fn write_str(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    write_bytes(writer, value.as_bytes())
+}
+
+fn read_str(reader: &mut impl Read) -> Result<String, EnvelopeError> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| EnvelopeError::Malformed("invalid name"))
+}
+
+fn write_columns<F: PrimeField>(writer: &mut impl Write, columns: &[Vec<F>]) -> io::Result<()> {
+    writer.write_all(&(columns.len() as u32).to_le_bytes())?;
+    for column in columns {
+        writer.write_all(&(column.len() as u32).to_le_bytes())?;
+        for value in column {
+            writer.write_all(value.to_repr().as_ref())?;
+        }
+    }
+    Ok(())
+}
+
+fn read_columns<F: PrimeField>(reader: &mut impl Read) -> Result<Vec<Vec<F>>, EnvelopeError> {
+    let n_columns = read_u32(reader)?;
+    (0..n_columns)
+        .map(|_| {
+            let len = read_u32(reader)?;
+            (0..len)
+                .map(|_| {
+                    let mut repr = F::Repr::default();
+                    reader.read_exact(repr.as_mut())?;
+                    Option::from(F::from_repr(repr))
+                        .ok_or(EnvelopeError::Malformed("non-canonical field element"))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+const PARAMS_LEN: usize = 39;
+
+/// Fixed-width encoding of the parameters, also hashed into fingerprints.
+fn params_bytes(params: &BrainfuckParams) -> [u8; PARAMS_LEN] {
+    let mut bytes = Vec::with_capacity(PARAMS_LEN);
+    bytes.push(params.cell_bits.is_some() as u8);
+    bytes.extend(params.cell_bits.unwrap_or(0).to_le_bytes());
+    for value in [
+        params.tape_size,
+        params.max_program_len,
+        params.max_steps,
+        params.steps_per_row,
+    ] {
+        bytes.extend((value as u64).to_le_bytes());
+    }
+    bytes.push(match params.io_mode {
+        IoMode::Public => 0,
+        IoMode::PrivateInput => 1,
+        IoMode::None => 2,
+    });
+    bytes.push(params.expose_final_clk as u8 | (params.expose_program as u8) << 1);
+    bytes.try_into().unwrap()
+}
+
+fn params_from_bytes(bytes: &[u8; PARAMS_LEN]) -> Result<BrainfuckParams, EnvelopeError> {
+    let malformed = EnvelopeError::Malformed("invalid circuit parameters");
+    let u64_at = |offset: usize| {
+        let value = u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        usize::try_from(value).map_err(|_| EnvelopeError::Malformed("invalid circuit parameters"))
+    };
+    let cell_bits = match bytes[0] {
+        0 => None,
+        1 => Some(u32::from_le_bytes(bytes[1..5].try_into().unwrap())),
+        _ => return Err(malformed),
+    };
+    let io_mode = match bytes[37] {
+        0 => IoMode::Public,
+        1 => IoMode::PrivateInput,
+        2 => IoMode::None,
+        _ => return Err(malformed),
+    };
+    // bit 0 exposes the final clk, bit 1 the program
+    if bytes[38] > 0b11 {
+        return Err(malformed);
+    }
+    let params = BrainfuckParams {
+        cell_bits,
+        tape_size: u64_at(5)?,
+        max_program_len: u64_at(13)?,
+        max_steps: u64_at(21)?,
+        steps_per_row: u64_at(29)?,
+        io_mode,
+        expose_final_clk: bytes[38] & 1 != 0,
+        expose_program: bytes[38] & 0b10 != 0,
+    };
+    // the circuit panics on parameters `BrainfuckParams::check` rejects
+    params
+        .check()
+        .map_err(|_| EnvelopeError::Malformed("invalid circuit parameters"))?;
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::halo2curves::pasta::Fp;
+
+    use super::*;
+    use crate::{
+        fixtures::{self, params, CODE},
+        prover::{verify, Ipa, Kzg, Prover},
+    };
+
+    #[test]
+    fn test_params_round_trip() {
+        for params in [
+            BrainfuckParams::default(),
+            BrainfuckParams {
+                cell_bits: Some(8),
+                io_mode: IoMode::PrivateInput,
+                steps_per_row: 3,
+                expose_final_clk: true,
+                expose_program: true,
+                ..params()
+            },
+        ] {
+            assert_eq!(params_from_bytes(&params_bytes(&params)).unwrap(), params);
+        }
+    }
+
+    #[test]
+    fn test_invalid_params_rejected() {
+        let k = params_k(&params(), Fp::S).unwrap();
+        for (params, k) in [
+            (
+                BrainfuckParams {
+                    max_steps: usize::MAX,
+                    ..params()
+                },
+                k,
+            ),
+            (
+                BrainfuckParams {
+                    cell_bits: Some(64),
+                    ..params()
+                },
+                k,
+            ),
+            (
+                BrainfuckParams {
+                    steps_per_row: 1 << 40,
+                    ..params()
+                },
+                k,
+            ),
+            // keys of valid parameters at a size they are never generated at
+            (params(), 40),
+            (params(), 64),
+        ] {
+            let mut bytes = vec![];
+            write_key_header::<Ipa>(&mut bytes, VK_MAGIC, &params, k).unwrap();
+            assert!(matches!(
+                read_vk::<Ipa>(&mut bytes.as_slice()),
+                Err(EnvelopeError::Malformed(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let prover = fixtures::prover(Ipa, &(), params());
+        let proof = prover.prove(&CODE, &[Fp::from(97)]).unwrap();
+
+        let mut bytes = vec![];
+        write_vk::<Ipa>(&mut bytes, &params(), Ipa::k(prover.setup()), prover.vk()).unwrap();
+        let vk = read_vk::<Ipa>(&mut bytes.as_slice()).unwrap();
+        assert_eq!(vk.params, params());
+        assert_eq!(vk.fingerprint, prover.fingerprint());
+
+        let mut bytes = vec![];
+        write_proof(&mut bytes, &Ipa, &vk.fingerprint, &proof).unwrap();
+        let read = read_proof(&mut bytes.as_slice(), &Ipa, &vk.fingerprint).unwrap();
+        assert_eq!(read, proof);
+        verify(&Ipa, prover.setup(), &vk.key, &read).unwrap();
+
+        let mut bytes = vec![];
+        write_instances(&mut bytes, &vk.fingerprint, &proof.instances).unwrap();
+        let instances = read_instances::<Fp>(&mut bytes.as_slice(), &vk.fingerprint).unwrap();
+        assert_eq!(instances, proof.instances);
+
+        // a prover loaded from its stored key makes proofs the VK accepts
+        let mut bytes = vec![];
+        write_pk::<Ipa>(&mut bytes, &params(), Ipa::k(prover.setup()), prover.pk()).unwrap();
+        let pk = read_pk::<Ipa>(&mut bytes.as_slice()).unwrap();
+        let k = pk.k;
+        let loaded = Prover::from_key(Ipa, Ipa::load_setup(&(), k).unwrap(), pk).unwrap();
+        let proof = loaded.prove(&CODE, &[Fp::from(97)]).unwrap();
+        verify(&Ipa, prover.setup(), &vk.key, &proof).unwrap();
+    }
+
+    #[test]
+    fn test_mismatches_rejected() {
+        let prover = fixtures::prover(Ipa, &(), params());
+        let proof = prover.prove(&CODE, &[Fp::from(97)]).unwrap();
+        let fingerprint = prover.fingerprint();
+        let mut bytes = vec![];
+        write_proof(&mut bytes, &Ipa, &fingerprint, &proof).unwrap();
+
+        let mut newer = bytes.clone();
+        newer[4] += 1;
+        assert!(matches!(
+            read_proof(&mut newer.as_slice(), &Ipa, &fingerprint),
+            Err(EnvelopeError::Version { found: 2, .. })
+        ));
+        assert!(matches!(
+            read_vk::<Ipa>(&mut bytes.as_slice()),
+            Err(EnvelopeError::Magic { .. })
+        ));
+        assert!(matches!(
+            read_proof(&mut bytes.as_slice(), &Kzg::default(), &fingerprint),
+            Err(EnvelopeError::Backend { .. })
+        ));
+        let other = Fingerprint::new::<Ipa>(&BrainfuckParams::default(), 10);
+        assert!(matches!(
+            read_proof(&mut bytes.as_slice(), &Ipa, &other),
+            Err(EnvelopeError::ConfigMismatch { .. })
+        ));
+        assert!(matches!(
+            read_proof(&mut &bytes[..bytes.len() - 1], &Ipa, &fingerprint),
+            Err(EnvelopeError::Malformed(_))
+        ));
+
+        // keys over another curve
+        let mut bytes = vec![];
+        write_vk::<Ipa>(&mut bytes, &params(), Ipa::k(prover.setup()), prover.vk()).unwrap();
+        assert!(matches!(
+            read_vk::<Kzg>(&mut bytes.as_slice()),
+            Err(EnvelopeError::Backend { .. })
+        ));
+    }
+}
@@ -0,0 +1,35 @@
+//! Parameters, setups and a program shared by the tests of the prover and
+//! of what it stores.
+
+use halo2_proofs::halo2curves::ff::PrimeField;
+use vm::interpreter::{ADD, GETCHAR, LB, PUTCHAR, RB, SHL, SHR, SUB};
+
+use crate::{
+    params::BrainfuckParams,
+    prover::{Backend, Prover},
+    sizing::params_k,
+};
+
+/// `++>,<[>+.<-]`: reads a value and prints the next two.
+pub const CODE: [u8; 12] = [
+    ADD, ADD, SHR, GETCHAR, SHL, LB, SHR, ADD, PUTCHAR, SHL, SUB, RB,
+];
+
+/// Parameters small enough for real keygen and proving to be quick.
+pub fn params() -> BrainfuckParams {
+    BrainfuckParams {
+        max_program_len: 32,
+        max_steps: 64,
+        ..BrainfuckParams::default()
+    }
+}
+
+/// A setup of the size circuits with `params` need.
+pub fn setup<B: Backend>(source: &B::Source, params: &BrainfuckParams) -> B::Setup {
+    B::load_setup(source, params_k(params, B::Scalar::S).unwrap()).unwrap()
+}
+
+pub fn prover<B: Backend>(backend: B, source: &B::Source, params: BrainfuckParams) -> Prover<B> {
+    let setup = setup::<B>(source, &params);
+    Prover::new(backend, setup, params).unwrap()
+}
@@ -0,0 +1,211 @@
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{Region, Value},
+    plonk::{Advice, Column, ConstraintSystem, ErrorFront, Expression, VirtualCells},
+    poly::Rotation,
+};
+
+#[derive(Clone, Debug)]
+pub struct IsZeroConfig<F: Field> {
+    pub value_inv: Column<Advice>,
+    is_zero_expr: Expression<F>,
+}
+impl<F: Field> IsZeroConfig<F> {
+    /// 1 when the value is zero and 0 otherwise, on rows where the chip is
+    /// enabled.
+    pub fn expr(&self) -> Expression<F> {
+        self.is_zero_expr.clone()
+    }
+}
+
+/// Proves whether a value is zero by witnessing its inverse (or 0):
+/// `is_zero = 1 - value * value_inv` with `value * is_zero = 0`.
+pub struct IsZeroChip<F: Field> {
+    config: IsZeroConfig<F>,
+}
+
+impl<F: Field> IsZeroChip<F> {
+    pub fn configure(
+        meta: &mut ConstraintSystem<F>,
+        q_enable: impl FnOnce(&mut VirtualCells<F>) -> Expression<F>,
+        value: impl FnOnce(&mut VirtualCells<F>) -> Expression<F>,
+        value_inv: Column<Advice>,
+    ) -> IsZeroConfig<F> {
+        let mut is_zero_expr = Expression::Constant(F::ZERO);
+
+        meta.create_gate("is zero", |meta| {
+            let q_enable = q_enable(meta);
+            let value = value(meta);
+            let value_inv = meta.query_advice(value_inv, Rotation::cur());
+
+            is_zero_expr = Expression::Constant(F::ONE) - value.clone() * value_inv;
+            vec![q_enable * value * is_zero_expr.clone()]
+        });
+
+        IsZeroConfig {
+            value_inv,
+            is_zero_expr,
+        }
+    }
+
+    pub fn construct(config: IsZeroConfig<F>) -> Self {
+        Self { config }
+    }
+
+    /// Witnesses the inverse of `value` (0 when `value` is 0) at `offset`.
+    pub fn assign(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        value: Value<F>,
+    ) -> Result<(), ErrorFront> {
+        let value_inv = value.map(|value| value.invert().unwrap_or(F::ZERO));
+        self.assign_inverse(region, offset, value_inv)
+    }
+
+    /// Witnesses `value_inv` as the inverse at `offset`, whatever the value.
+    pub fn assign_inverse(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        value_inv: Value<F>,
+    ) -> Result<(), ErrorFront> {
+        region.assign_advice(|| "value inv", self.config.value_inv, offset, || value_inv)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        dev::MockProver,
+        plonk::{Circuit, Selector},
+    };
+
+    use super::*;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    #[derive(Clone)]
+    struct TestConfig {
+        value: Column<Advice>,
+        expected: Column<Advice>,
+        q_enable: Selector,
+        is_zero: IsZeroConfig<Fr>,
+    }
+
+    #[derive(Default)]
+    struct TestCircuit {
+        // (value, claimed is_zero)
+        rows: Vec<(Fr, bool)>,
+        // witness this inverse instead of the real one
+        forged_inv: Option<Fr>,
+    }
+
+    impl Circuit<Fr> for TestCircuit {
+        type Config = TestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let value = meta.advice_column();
+            let expected = meta.advice_column();
+            let value_inv = meta.advice_column();
+            let q_enable = meta.selector();
+            let is_zero = IsZeroChip::configure(
+                meta,
+                |meta| meta.query_selector(q_enable),
+                |meta| meta.query_advice(value, Rotation::cur()),
+                value_inv,
+            );
+            meta.create_gate("is zero matches expected", |meta| {
+                let q_enable = meta.query_selector(q_enable);
+                let expected = meta.query_advice(expected, Rotation::cur());
+                vec![q_enable * (is_zero.expr() - expected)]
+            });
+            TestConfig {
+                value,
+                expected,
+                q_enable,
+                is_zero,
+            }
+        }
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), ErrorFront> {
+            let chip = IsZeroChip::construct(config.is_zero.clone());
+            layouter.assign_region(
+                || "is zero",
+                |mut region| {
+                    for (offset, &(value, expected)) in self.rows.iter().enumerate() {
+                        region.enable_selector(|| "q_enable", &config.q_enable, offset)?;
+                        region.assign_advice(
+                            || "value",
+                            config.value,
+                            offset,
+                            || Value::known(value),
+                        )?;
+                        region.assign_advice(
+                            || "expected",
+                            config.expected,
+                            offset,
+                            || Value::known(Fr::from(expected as u64)),
+                        )?;
+                        match self.forged_inv {
+                            Some(inv) => {
+                                region.assign_advice(
+                                    || "forged inv",
+                                    config.is_zero.value_inv,
+                                    offset,
+                                    || Value::known(inv),
+                                )?;
+                            }
+                            None => chip.assign(&mut region, offset, Value::known(value))?,
+                        }
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    fn verify(rows: Vec<(Fr, bool)>, forged_inv: Option<Fr>) -> bool {
+        let circuit = TestCircuit { rows, forged_inv };
+        MockProver::run(4, &circuit, vec![])
+            .unwrap()
+            .verify()
+            .is_ok()
+    }
+
+    #[test]
+    fn test_is_zero() {
+        assert!(verify(
+            vec![
+                (Fr::ZERO, true),
+                (Fr::ONE, false),
+                (Fr::from(255), false),
+                (-Fr::ONE, false),
+            ],
+            None,
+        ));
+    }
+
+    #[test]
+    fn test_wrong_claim_rejected() {
+        assert!(!verify(vec![(Fr::ZERO, false)], None));
+        assert!(!verify(vec![(Fr::from(7), true)], None));
+    }
+
+    #[test]
+    fn test_forged_inverse_rejected() {
+        // a zero inverse would make a non-zero value look like zero
+        assert!(!verify(vec![(Fr::from(7), true)], Some(Fr::ZERO)));
+        // any inverse of a non-inverse makes is_zero neither 0 nor 1
+        assert!(!verify(vec![(Fr::from(7), false)], Some(Fr::from(2))));
+        // a zero value is zero whatever inverse is witnessed
+        assert!(verify(vec![(Fr::ZERO, true)], Some(Fr::from(5))));
+    }
+}
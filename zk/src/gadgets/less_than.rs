@@ -1,47 +1,60 @@
 use halo2_proofs::{
-    halo2curves::bn256::Fr,
-    plonk::{Advice, Column, ConstraintSystem, Expression, Fixed, VirtualCells},
+    arithmetic::Field,
+    circuit::{Region, Value},
+    halo2curves::ff::PrimeField,
+    plonk::{Advice, Column, ConstraintSystem, ErrorFront, Expression, VirtualCells},
     poly::Rotation,
 };
-pub struct LtConfig<const N_BYTES: usize> {
+
+use super::range_check::RangeCheckConfig;
+#[derive(Clone, Copy, Debug)]
+pub struct LtConfig<F, const N_LIMBS: usize> {
     pub lt: Column<Advice>,
-    pub diff: [Column<Advice>; N_BYTES],
-    pub u8: Column<Fixed>,
-    pub range: Fr,
+    pub diff: [Column<Advice>; N_LIMBS],
+    pub limb_bits: usize,
+    pub range: F,
 }
-impl<const N_BYTES: usize> LtConfig<N_BYTES> {
-    pub fn is_lt(&self, meta: &mut VirtualCells<Fr>) -> Expression<Fr> {
+impl<F: PrimeField, const N_LIMBS: usize> LtConfig<F, N_LIMBS> {
+    pub fn is_lt(&self, meta: &mut VirtualCells<F>) -> Expression<F> {
         meta.query_advice(self.lt, Rotation::cur())
     }
 }
 
-pub struct LtChip<const N_BYTES: usize> {
-    config: LtConfig<N_BYTES>,
+/// Proves `lt = (lhs < rhs)` for values below `2^(N_LIMBS * bits)`, where
+/// `bits` is the width of the shared range table, by witnessing
+/// `lhs - rhs + lt * 2^(N_LIMBS * bits)` as `N_LIMBS` range-checked limbs.
+pub struct LtChip<F, const N_LIMBS: usize> {
+    config: LtConfig<F, N_LIMBS>,
 }
 
-impl<const N_BYTES: usize> LtChip<N_BYTES> {
+impl<F: PrimeField, const N_LIMBS: usize> LtChip<F, N_LIMBS> {
     pub fn configure(
-        meta: &mut ConstraintSystem<Fr>,
-        q_enable: impl FnOnce(&mut VirtualCells<Fr>) -> Expression<Fr>,
-        lhs: impl FnOnce(&mut VirtualCells<Fr>) -> Expression<Fr>,
-        rhs: impl FnOnce(&mut VirtualCells<Fr>) -> Expression<Fr>,
-    ) -> LtConfig<N_BYTES> {
+        meta: &mut ConstraintSystem<F>,
+        range_check: &RangeCheckConfig,
+        q_enable: impl FnOnce(&mut VirtualCells<F>) -> Expression<F>,
+        lhs: impl FnOnce(&mut VirtualCells<F>) -> Expression<F>,
+        rhs: impl FnOnce(&mut VirtualCells<F>) -> Expression<F>,
+    ) -> LtConfig<F, N_LIMBS> {
+        let limb_bits = range_check.bits;
+        assert!(
+            N_LIMBS > 0 && N_LIMBS * limb_bits < F::NUM_BITS as usize - 1,
+            "LtChip cannot compare {N_LIMBS} limbs of {limb_bits} bits"
+        );
         let lt = meta.advice_column();
-        let diff = [(); N_BYTES].map(|_| meta.advice_column());
-        let range = Fr::from(2u64.pow(N_BYTES as u32 * 8));
-        let u8 = meta.fixed_column();
+        let diff = [(); N_LIMBS].map(|_| meta.advice_column());
+        let range = F::from(2).pow_vartime([(N_LIMBS * limb_bits) as u64]);
 
         meta.create_gate("lt gate", |meta| {
             let q_enable = q_enable(meta);
             let lt = meta.query_advice(lt, Rotation::cur());
 
-            let diff_bytes = diff
+            let diff_limbs = diff
                 .iter()
                 .map(|c| meta.query_advice(*c, Rotation::cur()))
-                .collect::<Vec<Expression<Fr>>>();
+                .collect::<Vec<Expression<F>>>();
 
-            let check_a =
-                lhs(meta) - rhs(meta) - expr_from_bytes(&diff_bytes) + (lt.clone() * range);
+            let check_a = lhs(meta) - rhs(meta) - expr_from_limbs(&diff_limbs, limb_bits)
+                + (lt.clone() * range);
 
             let check_b = bool_check(lt);
 
@@ -50,37 +63,235 @@ impl<const N_BYTES: usize> LtChip<N_BYTES> {
                 .map(move |poly| q_enable.clone() * poly)
         });
 
-        meta.annotate_lookup_any_column(u8, || "LOOKUP_u8");
-
-        diff[0..N_BYTES].iter().for_each(|column| {
-            meta.lookup_any("range check for u8", |meta| {
-                let u8_cell = meta.query_advice(*column, Rotation::cur());
-                let u8_range = meta.query_fixed(u8, Rotation::cur());
-                vec![(u8_cell, u8_range)]
+        diff.iter().for_each(|&column| {
+            range_check.register(meta, "lt diff limb range check", |meta| {
+                meta.query_advice(column, Rotation::cur())
             });
         });
 
         LtConfig {
             lt,
             diff,
+            limb_bits,
             range,
-            u8,
         }
     }
 
-    pub fn construct(config: LtConfig<N_BYTES>) -> LtChip<N_BYTES> {
+    pub fn construct(config: LtConfig<F, N_LIMBS>) -> LtChip<F, N_LIMBS> {
         LtChip { config }
     }
+
+    /// Witnesses `lt` and `diff` at `offset` for the comparison `lhs < rhs`.
+    /// Both values must be below `2^(N_LIMBS * bits)`.
+    pub fn assign(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        lhs: F,
+        rhs: F,
+    ) -> Result<(), ErrorFront> {
+        self.assign_with_lt(region, offset, lhs, rhs, lt_repr(&lhs, &rhs))
+    }
+
+    fn assign_with_lt(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        lhs: F,
+        rhs: F,
+        lt: bool,
+    ) -> Result<(), ErrorFront> {
+        region.assign_advice(
+            || "lt",
+            self.config.lt,
+            offset,
+            || Value::known(F::from(lt as u64)),
+        )?;
+
+        let diff = lhs - rhs + if lt { self.config.range } else { F::ZERO };
+        let limbs = limbs_of(&diff, self.config.limb_bits, N_LIMBS);
+        for (idx, (column, limb)) in self.config.diff.iter().zip(limbs).enumerate() {
+            region.assign_advice(
+                || format!("diff limb {idx}"),
+                *column,
+                offset,
+                || Value::known(F::from(limb)),
+            )?;
+        }
+        Ok(())
+    }
 }
-pub fn expr_from_bytes(bytes: &[Expression<Fr>]) -> Expression<Fr> {
-    let mut value = Expression::Constant(Fr::zero());
-    let mut multiplier = Fr::one();
-    for byte in bytes.iter() {
-        value = value + byte.clone() * multiplier;
-        multiplier *= Fr::from(256);
+pub fn expr_from_limbs<F: PrimeField>(limbs: &[Expression<F>], limb_bits: usize) -> Expression<F> {
+    let mut value = Expression::Constant(F::ZERO);
+    let mut multiplier = F::ONE;
+    let base = F::from(1u64 << limb_bits);
+    for limb in limbs.iter() {
+        value = value + limb.clone() * multiplier;
+        multiplier *= base;
     }
     value
 }
-fn bool_check(value: Expression<Fr>) -> Expression<Fr> {
-    value.clone() * (value - Expression::Constant(Fr::one()))
+fn bool_check<F: PrimeField>(value: Expression<F>) -> Expression<F> {
+    value.clone() * (value - Expression::Constant(F::ONE))
+}
+/// Integer comparison of two field elements through their little-endian
+/// canonical representation.
+fn lt_repr<F: PrimeField>(lhs: &F, rhs: &F) -> bool {
+    let lhs = lhs.to_repr();
+    let rhs = rhs.to_repr();
+    lhs.as_ref().iter().rev().cmp(rhs.as_ref().iter().rev()) == std::cmp::Ordering::Less
+}
+/// The `n` lowest `limb_bits`-wide limbs of `value`, least significant first.
+pub(crate) fn limbs_of<F: PrimeField>(value: &F, limb_bits: usize, n: usize) -> Vec<u64> {
+    let repr = value.to_repr();
+    let bytes = repr.as_ref();
+    (0..n)
+        .map(|limb| {
+            (0..limb_bits).fold(0u64, |acc, bit| {
+                let pos = limb * limb_bits + bit;
+                let set = bytes.get(pos / 8).map_or(0, |byte| (byte >> (pos % 8)) & 1);
+                acc | ((set as u64) << bit)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        dev::MockProver,
+        plonk::{Circuit, Selector},
+    };
+
+    use super::*;
+    use crate::gadgets::range_check::{RangeCheckChip, RANGE_CHECK_BITS};
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    const N_LIMBS: usize = 2;
+
+    #[derive(Clone)]
+    struct TestConfig {
+        lhs: Column<Advice>,
+        rhs: Column<Advice>,
+        q_enable: Selector,
+        range_check: RangeCheckConfig,
+        lt: LtConfig<Fr, N_LIMBS>,
+    }
+
+    #[derive(Default)]
+    struct TestCircuit {
+        pairs: Vec<(u64, u64)>,
+        // witness the opposite of the real result (with a matching diff)
+        forge_lt: bool,
+    }
+
+    impl Circuit<Fr> for TestCircuit {
+        type Config = TestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+        fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+            let lhs = meta.advice_column();
+            let rhs = meta.advice_column();
+            let q_enable = meta.selector();
+            let range_check = RangeCheckChip::configure(meta, RANGE_CHECK_BITS);
+            let lt = LtChip::configure(
+                meta,
+                &range_check,
+                |meta| meta.query_selector(q_enable),
+                |meta| meta.query_advice(lhs, Rotation::cur()),
+                |meta| meta.query_advice(rhs, Rotation::cur()),
+            );
+            TestConfig {
+                lhs,
+                rhs,
+                q_enable,
+                range_check,
+                lt,
+            }
+        }
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), ErrorFront> {
+            RangeCheckChip::construct(config.range_check).load(&mut layouter)?;
+            let chip = LtChip::construct(config.lt);
+            layouter.assign_region(
+                || "comparisons",
+                |mut region| {
+                    for (offset, &(lhs, rhs)) in self.pairs.iter().enumerate() {
+                        region.enable_selector(|| "q_enable", &config.q_enable, offset)?;
+                        region.assign_advice(
+                            || "lhs",
+                            config.lhs,
+                            offset,
+                            || Value::known(Fr::from(lhs)),
+                        )?;
+                        region.assign_advice(
+                            || "rhs",
+                            config.rhs,
+                            offset,
+                            || Value::known(Fr::from(rhs)),
+                        )?;
+                        let lt = (lhs < rhs) != self.forge_lt;
+                        chip.assign_with_lt(&mut region, offset, Fr::from(lhs), Fr::from(rhs), lt)?;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    const MAX: u64 = (1 << (RANGE_CHECK_BITS * N_LIMBS)) - 1;
+
+    fn verify(pairs: Vec<(u64, u64)>, forge_lt: bool) -> bool {
+        let circuit = TestCircuit { pairs, forge_lt };
+        MockProver::run(9, &circuit, vec![])
+            .unwrap()
+            .verify()
+            .is_ok()
+    }
+
+    #[test]
+    fn test_lt_boundaries() {
+        assert!(verify(
+            vec![
+                (0, 0),
+                (0, 1),
+                (1, 0),
+                (5, 5),
+                (0, MAX),
+                (MAX, 0),
+                (MAX - 1, MAX),
+                (MAX, MAX)
+            ],
+            false,
+        ));
+    }
+
+    #[test]
+    fn test_lt_result() {
+        let config = {
+            let mut meta = ConstraintSystem::default();
+            TestCircuit::configure(&mut meta).lt
+        };
+        assert_eq!(config.range, Fr::from(MAX + 1));
+        assert!(lt_repr(&Fr::from(0), &Fr::from(1)));
+        assert!(!lt_repr(&Fr::from(1), &Fr::from(1)));
+        assert!(!lt_repr(&Fr::from(256), &Fr::from(255)));
+        assert!(lt_repr(&Fr::from(255), &Fr::from(256)));
+        assert_eq!(limbs_of(&Fr::from(0x1234), 8, 3), vec![0x34, 0x12, 0]);
+        assert_eq!(limbs_of(&Fr::from(0x1234), 4, 2), vec![0x4, 0x3]);
+    }
+
+    #[test]
+    fn test_forged_lt_rejected() {
+        assert!(!verify(vec![(3, 5)], true));
+        assert!(!verify(vec![(5, 3)], true));
+        assert!(!verify(vec![(4, 4)], true));
+        assert!(!verify(vec![(0, MAX)], true));
+    }
 }
@@ -1 +1,3 @@
+pub mod is_zero;
 pub mod less_than;
+pub mod range_check;
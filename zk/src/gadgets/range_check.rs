@@ -0,0 +1,76 @@
+use halo2_proofs::{
+    circuit::{Layouter, Value},
+    halo2curves::ff::PrimeField,
+    plonk::{ConstraintSystem, ErrorFront, Expression, TableColumn, VirtualCells},
+};
+
+/// Bit width of the circuit-wide range table.
+pub const RANGE_CHECK_BITS: usize = 8;
+
+/// A single `0..2^bits` lookup table shared by every chip that needs a range
+/// check. Chips register the expressions they want checked instead of
+/// allocating their own fixed column.
+#[derive(Clone, Copy, Debug)]
+pub struct RangeCheckConfig {
+    pub table: TableColumn,
+    pub bits: usize,
+}
+impl RangeCheckConfig {
+    /// Number of values in the table, `2^bits`.
+    pub fn size(&self) -> usize {
+        1 << self.bits
+    }
+
+    /// Constrains `value` to `0..2^bits` on every row. The expression must
+    /// evaluate to a value in range (typically 0) on rows it does not use.
+    pub fn register<F: PrimeField>(
+        &self,
+        meta: &mut ConstraintSystem<F>,
+        name: &'static str,
+        value: impl FnOnce(&mut VirtualCells<F>) -> Expression<F>,
+    ) {
+        let table = self.table;
+        meta.lookup(name, |meta| vec![(value(meta), table)]);
+    }
+}
+
+pub struct RangeCheckChip {
+    config: RangeCheckConfig,
+}
+
+impl RangeCheckChip {
+    pub fn configure<F: PrimeField>(
+        meta: &mut ConstraintSystem<F>,
+        bits: usize,
+    ) -> RangeCheckConfig {
+        assert!(
+            bits > 0 && bits <= 24,
+            "range table must have 1 to 24 bits, got {bits}"
+        );
+        let table = meta.lookup_table_column();
+        meta.annotate_lookup_column(table, || "LOOKUP_range");
+        RangeCheckConfig { table, bits }
+    }
+
+    pub fn construct(config: RangeCheckConfig) -> Self {
+        Self { config }
+    }
+
+    /// Loads `0..2^bits` into the table. Called once per circuit.
+    pub fn load<F: PrimeField>(&self, layouter: &mut impl Layouter<F>) -> Result<(), ErrorFront> {
+        layouter.assign_table(
+            || "range table",
+            |mut table| {
+                for value in 0..self.config.size() {
+                    table.assign_cell(
+                        || "range",
+                        self.config.table,
+                        value,
+                        || Value::known(F::from(value as u64)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
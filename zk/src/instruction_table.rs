@@ -2,94 +2,233 @@ use std::env::consts;
 
 use halo2_proofs::{
     arithmetic::Field,
-    circuit::Value,
-    halo2curves::bn256::Fr,
-    plonk::{Advice, Column, ConstraintSystem, Expression, Selector},
+    circuit::{Region, Value},
+    halo2curves::ff::PrimeField,
+    plonk::{Advice, Column, ConstraintSystem, ErrorFront, Expression, Instance, Selector},
     poly::Rotation,
 };
 use vm::{
     interpreter::{ADD, GETCHAR, LB, PUTCHAR, RB, SHL, SHR, SUB},
-    table::Tables,
+    table::{InstructionTableRow, Tables},
 };
-#[derive(Clone)]
 
-pub struct InstructionTableConfig {
+use crate::{
+    padding::{configure_enable, next_slot, transition},
+    table::{
+        assign_public, Annotations, Argument, Export, PublicCell, Table, TableContext, Witness,
+    },
+};
+/// Columns of one logical row. A row holds `steps_per_row` of them, one per
+/// slot.
+#[derive(Clone, Copy)]
+pub struct InstructionSlot {
     pub ip: Column<Advice>,
     pub ci: Column<Advice>,
     pub ni: Column<Advice>,
-    pub s_i: Selector,
+    pub enable: Column<Advice>,
+    /// public program bytes of the slot's logical rows, one per row, which
+    /// `ci` copies when the program is public
+    pub program: Option<Column<Instance>>,
 }
-pub struct InstructionTableChip {
-    config: InstructionTableConfig,
+#[derive(Clone)]
+
+pub struct InstructionTableConfig {
+    pub slots: Vec<InstructionSlot>,
+    pub s_i: Selector,
+    pub q_row: Selector,
+    /// first row, when the program is public
+    pub q_first: Option<Selector>,
 }
-impl InstructionTableChip {
-    pub fn construct(config: InstructionTableConfig) -> Self {
-        Self { config }
-    }
-    pub fn configure(meta: &mut ConstraintSystem<Fr>) -> InstructionTableConfig {
-        let ip = meta.advice_column();
-        let ci = meta.advice_column();
-        let ni = meta.advice_column();
+pub struct InstructionTable;
+impl<F: PrimeField> Table<F> for InstructionTable {
+    const NAME: &'static str = "instruction";
+    type Config = InstructionTableConfig;
+    type Row = InstructionTableRow;
+
+    fn configure(meta: &mut ConstraintSystem<F>, ctx: &TableContext<'_>) -> InstructionTableConfig {
+        let public = ctx.params.expose_program;
+        let n_slots = ctx.params.steps_per_row;
         let s_i = meta.selector();
-        let ONE = Expression::Constant(Fr::ONE);
-        meta.create_gate("instruction table transition constraints", |meta| {
-            let ip_add_one = meta.query_advice(ip, Rotation::next())
-                - meta.query_advice(ip, Rotation::cur())
-                - ONE;
-            let s_i_cell = meta.query_selector(s_i);
+        let q_row = meta.selector();
+        let slots = configure_enable(meta, q_row, s_i, n_slots)
+            .into_iter()
+            .map(|enable| {
+                let (ip, ci, ni) = (
+                    meta.advice_column(),
+                    meta.advice_column(),
+                    meta.advice_column(),
+                );
+                let program = public.then(|| {
+                    let program = meta.instance_column();
+                    meta.enable_equality(ci);
+                    meta.enable_equality(program);
+                    program
+                });
+                InstructionSlot {
+                    ip,
+                    ci,
+                    ni,
+                    enable,
+                    program,
+                }
+            })
+            .collect::<Vec<_>>();
+        let ONE = Expression::Constant(F::ONE);
+        for slot in 0..n_slots {
+            let cur = slots[slot];
+            let (next_index, rotation) = next_slot(slot, n_slots);
+            let next = slots[next_index];
+            meta.create_gate("instruction table transition constraints", |meta| {
+                let ip_add_one = meta.query_advice(next.ip, rotation)
+                    - meta.query_advice(cur.ip, Rotation::cur())
+                    - ONE.clone();
+                let s_i_cell = transition(meta, slot, n_slots, q_row, s_i)
+                    * meta.query_advice(next.enable, rotation);
 
-            vec![
-                s_i_cell.clone()
-                    * ip_add_one.clone()
-                    * (meta.query_advice(ip, Rotation::next())
-                        - meta.query_advice(ip, Rotation::cur())),
-                s_i_cell.clone()
-                    * ip_add_one.clone()
-                    * (meta.query_advice(ci, Rotation::next())
-                        - meta.query_advice(ci, Rotation::cur())),
-                s_i_cell
-                    * ip_add_one
-                    * (meta.query_advice(ni, Rotation::next())
-                        - meta.query_advice(ni, Rotation::cur())),
-            ]
+                vec![
+                    s_i_cell.clone()
+                        * ip_add_one.clone()
+                        * (meta.query_advice(next.ip, rotation)
+                            - meta.query_advice(cur.ip, Rotation::cur())),
+                    s_i_cell.clone()
+                        * ip_add_one.clone()
+                        * (meta.query_advice(next.ci, rotation)
+                            - meta.query_advice(cur.ci, Rotation::cur())),
+                    s_i_cell
+                        * ip_add_one
+                        * (meta.query_advice(next.ni, rotation)
+                            - meta.query_advice(cur.ni, Rotation::cur())),
+                ]
+            });
+            if public {
+                // Logical row i holds position i of the public program: ips
+                // count up by one, and ni is the next position's ci.
+                meta.create_gate("instruction table public program", |meta| {
+                    let s_i_cell = transition(meta, slot, n_slots, q_row, s_i)
+                        * meta.query_advice(next.enable, rotation);
+                    vec![
+                        s_i_cell.clone()
+                            * (meta.query_advice(next.ip, rotation)
+                                - meta.query_advice(cur.ip, Rotation::cur())
+                                - ONE.clone()),
+                        s_i_cell
+                            * (meta.query_advice(cur.ni, Rotation::cur())
+                                - meta.query_advice(next.ci, rotation)),
+                    ]
+                });
+            }
+        }
+        let q_first = public.then(|| {
+            let q_first = meta.selector();
+            let first = slots[0];
+            meta.create_gate("instruction table first ip", |meta| {
+                let q_first = meta.query_selector(q_first);
+                vec![q_first * meta.query_advice(first.ip, Rotation::cur())]
+            });
+            q_first
         });
 
-        InstructionTableConfig { ip, ci, ni, s_i }
+        InstructionTableConfig {
+            slots,
+            s_i,
+            q_row,
+            q_first,
+        }
+    }
+    fn exports(config: &InstructionTableConfig) -> Vec<Export<F>> {
+        let slots = config.slots.clone();
+        vec![Export::new(
+            "instruction.program",
+            slots.len(),
+            move |meta, slot| {
+                let InstructionSlot {
+                    ip, ci, ni, enable, ..
+                } = slots[slot];
+                let enable = meta.query_advice(enable, Rotation::cur());
+                vec![
+                    enable.clone() * meta.query_advice(ip, Rotation::cur()),
+                    enable.clone() * meta.query_advice(ci, Rotation::cur()),
+                    enable * meta.query_advice(ni, Rotation::cur()),
+                ]
+            },
+        )]
+    }
+    fn annotate(config: &InstructionTableConfig, columns: &mut Annotations) {
+        for (slot, slot_columns) in config.slots.iter().enumerate() {
+            columns.column("ip", slot, slot_columns.ip);
+            columns.column("ci", slot, slot_columns.ci);
+            columns.column("ni", slot, slot_columns.ni);
+            columns.column("enable", slot, slot_columns.enable);
+            if let Some(program) = slot_columns.program {
+                columns.column("program", slot, program);
+            }
+        }
     }
-    pub fn assign(
-        &self,
-        mut layouter: impl halo2_proofs::circuit::Layouter<Fr>,
-        tables: &Tables,
-    ) -> Result<(), halo2_proofs::plonk::ErrorFront> {
-        layouter.assign_region(
-            || "instruction table",
-            |mut region| {
-                for (offset, row) in tables.processor_table.iter().enumerate() {
-                    region.assign_advice(
-                        || "ip",
-                        self.config.ip,
-                        offset,
-                        || Value::known(Fr::from(row.ip as u64)),
-                    )?;
-                    region.assign_advice(
-                        || "ci",
-                        self.config.ci,
-                        offset,
-                        || Value::known(Fr::from(row.ci as u64)),
-                    )?;
-                    region.assign_advice(
-                        || "ni",
-                        self.config.ni,
-                        offset,
-                        || Value::known(Fr::from(row.ni as u64)),
-                    )?;
-                    if offset != tables.memory_table.len() - 1 {
-                        region.enable_selector(|| "s_m", &self.config.s_i, offset)?;
-                    }
+    fn arguments() -> Vec<Argument> {
+        vec![Argument::lookup(
+            "instruction table lookup constraints",
+            "processor.instruction",
+            "instruction.program",
+        )]
+    }
+    fn rows(tables: &Tables<F>) -> &[InstructionTableRow] {
+        &tables.instruction_table
+    }
+    fn empty_row() -> InstructionTableRow {
+        InstructionTableRow {
+            ip: 0,
+            ci: 0,
+            ni: 0,
+        }
+    }
+    fn assign(
+        config: &InstructionTableConfig,
+        region: &mut Region<'_, F>,
+        _witness: &Witness<'_, F>,
+        rows: &[(InstructionTableRow, bool)],
+    ) -> Result<Vec<PublicCell<F>>, ErrorFront> {
+        let n_slots = config.slots.len();
+        let height = rows.len() / n_slots;
+        if let Some(q_first) = &config.q_first {
+            region.enable_selector(|| "instruction first", q_first, 0)?;
+        }
+        for (index, (row, enable)) in rows.iter().enumerate() {
+            let (offset, slot) = (index / n_slots, index % n_slots);
+            let columns = &config.slots[slot];
+            region.assign_advice(
+                || "ip",
+                columns.ip,
+                offset,
+                || Value::known(F::from(row.ip as u64)),
+            )?;
+            assign_public(
+                region,
+                "ci",
+                columns.ci,
+                columns.program,
+                offset,
+                F::from(row.ci as u64),
+            )?;
+            region.assign_advice(
+                || "ni",
+                columns.ni,
+                offset,
+                || Value::known(F::from(row.ni as u64)),
+            )?;
+            region.assign_advice(
+                || "enable",
+                columns.enable,
+                offset,
+                || Value::known(F::from(*enable as u64)),
+            )?;
+            if slot == 0 {
+                region.enable_selector(|| "q_row", &config.q_row, offset)?;
+                if offset != height - 1 {
+                    region.enable_selector(|| "s_i", &config.s_i, offset)?;
                 }
+            }
+        }
 
-                Ok(())
-            },
-        )
+        Ok(vec![])
     }
 }
@@ -0,0 +1,366 @@
+use std::marker::PhantomData;
+
+use crate::{
+    circuit::{INPUT_LEN_ROW, OUTPUT_LEN_ROW},
+    gadgets::less_than::{LtChip, LtConfig},
+    padding::{configure_enable, next_slot, transition},
+    params::{BrainfuckParams, IoMode},
+    table::{
+        assign_public, Annotations, Argument, Export, PublicCell, SlotTag, Table, TableContext,
+        Witness,
+    },
+};
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{Region, Value},
+    halo2curves::ff::PrimeField,
+    plonk::{Advice, Column, ConstraintSystem, ErrorFront, Expression, Instance, Selector},
+    poly::Rotation,
+};
+use vm::{
+    interpreter::{GETCHAR, PUTCHAR},
+    table::{InputTableRow, OutputTableRow, Tables},
+};
+
+/// The steps an I/O table records, one logical row per step in clk order
+/// with the value read or printed. The input and output tables only differ
+/// in these.
+pub trait IoKind<F: PrimeField>: 'static {
+    /// See `Table::NAME`.
+    const NAME: &'static str;
+    /// Names of the table's exports: its rows as the steps they are, and as
+    /// rows.
+    const EXPORTS: [&'static str; 2];
+    /// Opcode of the recorded steps.
+    const OPCODE: u8;
+    /// Row of the `summary` instance column holding the number of rows.
+    const LEN_ROW: usize;
+    /// Lookups between the rows and the processor table's steps, both ways.
+    const ARGUMENTS: [Argument; 2];
+    type Row: Clone;
+
+    /// Whether the values are public, copied from instance columns.
+    fn is_public(params: &BrainfuckParams) -> bool;
+    fn rows(tables: &Tables<F>) -> &[Self::Row];
+    fn row(clk: u64, value: F) -> Self::Row;
+    /// The clk and value of `row`.
+    fn fields(row: &Self::Row) -> (u64, F);
+}
+
+/// Values read by GETCHAR steps.
+pub struct Input;
+impl<F: PrimeField> IoKind<F> for Input {
+    const NAME: &'static str = "input";
+    const EXPORTS: [&'static str; 2] = ["input.steps", "input.rows"];
+    const OPCODE: u8 = GETCHAR;
+    const LEN_ROW: usize = INPUT_LEN_ROW;
+    const ARGUMENTS: [Argument; 2] = [
+        // every input row is a GETCHAR step whose next cell value is the input
+        Argument::lookup(
+            "input table: row is a GETCHAR step",
+            "input.steps",
+            "processor.getchar",
+        ),
+        Argument::lookup(
+            "input table: GETCHAR step has an input row",
+            "processor.getchar_steps",
+            "input.rows",
+        ),
+    ];
+    type Row = InputTableRow<F>;
+
+    fn is_public(params: &BrainfuckParams) -> bool {
+        params.io_mode == IoMode::Public
+    }
+    fn rows(tables: &Tables<F>) -> &[InputTableRow<F>] {
+        &tables.input_table
+    }
+    fn row(clk: u64, value: F) -> InputTableRow<F> {
+        InputTableRow { clk, value }
+    }
+    fn fields(row: &InputTableRow<F>) -> (u64, F) {
+        (row.clk, row.value)
+    }
+}
+
+/// Values printed by PUTCHAR steps.
+pub struct Output;
+impl<F: PrimeField> IoKind<F> for Output {
+    const NAME: &'static str = "output";
+    const EXPORTS: [&'static str; 2] = ["output.steps", "output.rows"];
+    const OPCODE: u8 = PUTCHAR;
+    const LEN_ROW: usize = OUTPUT_LEN_ROW;
+    const ARGUMENTS: [Argument; 2] = [
+        // every output row is a PUTCHAR step that prints the current cell
+        Argument::lookup(
+            "output table: row is a PUTCHAR step",
+            "output.steps",
+            "processor.putchar",
+        ),
+        Argument::lookup(
+            "output table: PUTCHAR step has an output row",
+            "processor.putchar_steps",
+            "output.rows",
+        ),
+    ];
+    type Row = OutputTableRow<F>;
+
+    /// Outputs are public in every I/O mode.
+    fn is_public(_params: &BrainfuckParams) -> bool {
+        true
+    }
+    fn rows(tables: &Tables<F>) -> &[OutputTableRow<F>] {
+        &tables.output_table
+    }
+    fn row(clk: u64, value: F) -> OutputTableRow<F> {
+        OutputTableRow { clk, value }
+    }
+    fn fields(row: &OutputTableRow<F>) -> (u64, F) {
+        (row.clk, row.value)
+    }
+}
+
+pub type InputTable = IoTable<Input>;
+pub type OutputTable = IoTable<Output>;
+
+/// Columns of one logical row. A row holds `steps_per_row` of them, one per
+/// slot.
+#[derive(Clone, Copy)]
+pub struct IoSlot<F: PrimeField> {
+    pub clk: Column<Advice>,
+    /// copy of the slot's `instance` cell on the same row when the values
+    /// are public, and a private witness otherwise
+    pub value: Column<Advice>,
+    /// number of rows up to and including this one
+    pub count: Column<Advice>,
+    pub enable: Column<Advice>,
+    /// public values of the slot's logical rows, one per row
+    pub instance: Option<Column<Instance>>,
+    pub lt: LtConfig<F, 4>,
+    /// slot of the processor table holding this step, `clk % steps_per_row`
+    pub clk_tag: SlotTag,
+}
+#[derive(Clone)]
+pub struct IoTableConfig<F: PrimeField> {
+    pub slots: Vec<IoSlot<F>>,
+    pub s: Selector,
+    pub q_row: Selector,
+    pub q_first: Selector,
+}
+pub struct IoTable<K>(PhantomData<K>);
+impl<F: PrimeField, K: IoKind<F>> Table<F> for IoTable<K> {
+    const NAME: &'static str = K::NAME;
+    type Config = IoTableConfig<F>;
+    type Row = K::Row;
+
+    fn is_enabled(params: &BrainfuckParams) -> bool {
+        params.has_io()
+    }
+    fn configure(meta: &mut ConstraintSystem<F>, ctx: &TableContext<'_>) -> IoTableConfig<F> {
+        let range_check = ctx.range_check;
+        let public = K::is_public(ctx.params);
+        let n_slots = ctx.params.steps_per_row;
+        let s = meta.selector();
+        let q_row = meta.selector();
+        let q_first = meta.selector();
+        let columns = configure_enable(meta, q_row, s, n_slots)
+            .into_iter()
+            .map(|enable| {
+                let (clk, value, count) = (
+                    meta.advice_column(),
+                    meta.advice_column(),
+                    meta.advice_column(),
+                );
+                meta.enable_equality(value);
+                meta.enable_equality(count);
+                let instance = public.then(|| meta.instance_column());
+                if let Some(instance) = instance {
+                    meta.enable_equality(instance);
+                }
+                (clk, value, count, enable, instance)
+            })
+            .collect::<Vec<_>>();
+
+        let mut slots = Vec::with_capacity(n_slots);
+        for slot in 0..n_slots {
+            let (clk, value, count, enable, instance) = columns[slot];
+            let (next_index, rotation) = next_slot(slot, n_slots);
+            let (next_clk, _, next_count, next_enable, _) = columns[next_index];
+
+            let lt_config: LtConfig<F, 4> = LtChip::configure(
+                meta,
+                range_check,
+                |cell| {
+                    transition(cell, slot, n_slots, q_row, s)
+                        * cell.query_advice(next_enable, rotation)
+                },
+                |cell| cell.query_advice(clk, Rotation::cur()),
+                |cell| cell.query_advice(next_clk, rotation),
+            );
+            meta.create_gate("cur clk < next clk", |meta| {
+                let s = transition(meta, slot, n_slots, q_row, s)
+                    * meta.query_advice(next_enable, rotation);
+                let lt = meta.query_advice(lt_config.lt, Rotation::cur());
+                vec![s * (lt - Expression::Constant(F::ONE))]
+            });
+
+            // Logical row i holds the i-th value and the last row's count is
+            // the number of enabled rows, which is exposed as the public
+            // length.
+            meta.create_gate(format!("{} table length", K::NAME), |meta| {
+                let s = transition(meta, slot, n_slots, q_row, s);
+                let cur_count = meta.query_advice(count, Rotation::cur());
+                let next_count = meta.query_advice(next_count, rotation);
+                let next_enable = meta.query_advice(next_enable, rotation);
+                vec![s * (next_count - cur_count - next_enable)]
+            });
+
+            slots.push(IoSlot {
+                clk,
+                value,
+                count,
+                enable,
+                instance,
+                lt: lt_config,
+                clk_tag: SlotTag::configure(meta, q_row, n_slots),
+            });
+        }
+        let first = slots[0];
+        meta.create_gate(format!("{} table first count", K::NAME), |meta| {
+            let q_first = meta.query_selector(q_first);
+            let cur_count = meta.query_advice(first.count, Rotation::cur());
+            let cur_enable = meta.query_advice(first.enable, Rotation::cur());
+            vec![q_first * (cur_count - cur_enable)]
+        });
+
+        IoTableConfig {
+            slots,
+            s,
+            q_row,
+            q_first,
+        }
+    }
+    fn exports(config: &IoTableConfig<F>) -> Vec<Export<F>> {
+        let [steps_name, rows_name] = K::EXPORTS;
+        let n_slots = config.slots.len();
+        let slots = config.slots.clone();
+        let steps = Export::new(steps_name, n_slots, move |meta, slot| {
+            let IoSlot {
+                clk, value, enable, ..
+            } = slots[slot];
+            let enable = meta.query_advice(enable, Rotation::cur());
+            vec![
+                enable.clone() * meta.query_advice(clk, Rotation::cur()),
+                enable.clone() * Expression::Constant(F::from(K::OPCODE as u64)),
+                enable * meta.query_advice(value, Rotation::cur()),
+            ]
+        })
+        .tagged({
+            let slots = config.slots.clone();
+            move |meta, slot| slots[slot].clk_tag.expr(meta)
+        });
+        let slots = config.slots.clone();
+        let rows = Export::new(rows_name, n_slots, move |meta, slot| {
+            let IoSlot {
+                clk, value, enable, ..
+            } = slots[slot];
+            let enable = meta.query_advice(enable, Rotation::cur());
+            vec![
+                enable.clone(),
+                enable.clone() * meta.query_advice(clk, Rotation::cur()),
+                enable * meta.query_advice(value, Rotation::cur()),
+            ]
+        });
+        vec![steps, rows]
+    }
+    fn annotate(config: &IoTableConfig<F>, columns: &mut Annotations) {
+        for (slot, slot_columns) in config.slots.iter().enumerate() {
+            columns.column("clk", slot, slot_columns.clk);
+            columns.column("value", slot, slot_columns.value);
+            columns.column("count", slot, slot_columns.count);
+            columns.column("enable", slot, slot_columns.enable);
+            columns.lt("clk_lt", slot, &slot_columns.lt);
+            columns.tag("clk_tag", slot, &slot_columns.clk_tag);
+            if let Some(instance) = slot_columns.instance {
+                columns.column("instance", slot, instance);
+            }
+        }
+    }
+    fn arguments() -> Vec<Argument> {
+        K::ARGUMENTS.to_vec()
+    }
+    fn rows(tables: &Tables<F>) -> &[K::Row] {
+        K::rows(tables)
+    }
+    fn empty_row() -> K::Row {
+        K::row(0, F::ZERO)
+    }
+    /// Returns the cell holding the table length, to be constrained against
+    /// the public length.
+    fn assign(
+        config: &IoTableConfig<F>,
+        region: &mut Region<'_, F>,
+        _witness: &Witness<'_, F>,
+        rows: &[(K::Row, bool)],
+    ) -> Result<Vec<PublicCell<F>>, ErrorFront> {
+        let n_slots = config.slots.len();
+        let height = rows.len() / n_slots;
+        let lt_chips = config
+            .slots
+            .iter()
+            .map(|slot| LtChip::construct(slot.lt))
+            .collect::<Vec<_>>();
+        let mut count = 0u64;
+        let mut count_cell = None;
+        region.enable_selector(|| format!("{} first", K::NAME), &config.q_first, 0)?;
+        for (index, (row, enable)) in rows.iter().enumerate() {
+            let (offset, slot) = (index / n_slots, index % n_slots);
+            let columns = &config.slots[slot];
+            let (clk, value) = K::fields(row);
+            count += *enable as u64;
+            region.assign_advice(|| "clk", columns.clk, offset, || Value::known(F::from(clk)))?;
+            assign_public(
+                region,
+                "value",
+                columns.value,
+                columns.instance,
+                offset,
+                value,
+            )?;
+            count_cell = Some(region.assign_advice(
+                || "count",
+                columns.count,
+                offset,
+                || Value::known(F::from(count)),
+            )?);
+            region.assign_advice(
+                || "enable",
+                columns.enable,
+                offset,
+                || Value::known(F::from(*enable as u64)),
+            )?;
+            columns
+                .clk_tag
+                .assign(region, offset, clk as usize % n_slots)?;
+            if let Some((next, _)) = rows.get(index + 1) {
+                let (next_clk, _) = K::fields(next);
+                lt_chips[slot].assign(region, offset, F::from(clk), F::from(next_clk))?;
+            }
+            if slot == 0 {
+                region.enable_selector(|| format!("{} row", K::NAME), &config.q_row, offset)?;
+                if offset != height - 1 {
+                    region.enable_selector(
+                        || format!("{} selector", K::NAME),
+                        &config.s,
+                        offset,
+                    )?;
+                }
+            }
+        }
+
+        Ok(vec![PublicCell {
+            row: K::LEN_ROW,
+            cell: count_cell.expect("padded table has at least one row"),
+        }])
+    }
+}
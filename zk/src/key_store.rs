@@ -0,0 +1,325 @@
+//! On-disk store of proving and verifying keys.
+//!
+//! Keys only depend on the circuit configuration, the circuit size and the
+//! setup parameters, not on the program proven, so they are generated once
+//! and loaded afterwards. Files are named by the configuration fingerprint
+//! and a `SetupDigest`. A file that does not load as a key of the
+//! expected configuration, because it is stale, truncated or of an older
+//! format, is regenerated.
+
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use halo2_proofs::{
+    plonk::{ProvingKey, VerifyingKey},
+    poly::commitment::Params,
+};
+
+use crate::{
+    envelope::{read_pk, read_vk, write_pk, write_vk, EnvelopeError, Fingerprint, StoredKey},
+    params::BrainfuckParams,
+    prover::{check_setup, keygen, Backend, Prover, ProverError},
+};
+
+#[derive(Debug)]
+pub enum KeyStoreError {
+    Io(io::Error),
+    /// A key could not be written.
+    Envelope(EnvelopeError),
+    /// The setup does not fit the parameters, or key generation failed.
+    Prover(ProverError),
+}
+
+impl fmt::Display for KeyStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyStoreError::Io(error) => write!(f, "key store: {error}"),
+            KeyStoreError::Envelope(error) => write!(f, "key store: {error}"),
+            KeyStoreError::Prover(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for KeyStoreError {}
+
+impl From<io::Error> for KeyStoreError {
+    fn from(error: io::Error) -> Self {
+        KeyStoreError::Io(error)
+    }
+}
+impl From<EnvelopeError> for KeyStoreError {
+    fn from(error: EnvelopeError) -> Self {
+        KeyStoreError::Envelope(error)
+    }
+}
+impl From<ProverError> for KeyStoreError {
+    fn from(error: ProverError) -> Self {
+        KeyStoreError::Prover(error)
+    }
+}
+
+/// Keys stored in a directory.
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+/// Digest of setup parameters, naming the keys made under them: keys made
+/// under other parameters of the same size only verify under those.
+/// Computing it serializes the whole setup, so it is computed once per setup
+/// and passed to the store along with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SetupDigest([u8; 8]);
+
+impl SetupDigest {
+    pub fn new<B: Backend>(setup: &B::Setup) -> io::Result<Self> {
+        let mut state = blake2b_simd::Params::new()
+            .hash_length(8)
+            .personal(b"brainfuck-setup")
+            .to_state();
+        setup.write(&mut state)?;
+        Ok(Self(state.finalize().as_bytes().try_into().unwrap()))
+    }
+}
+
+impl fmt::Display for SetupDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+/// Where the keys of one configuration and setup are stored.
+struct Entry {
+    fingerprint: Fingerprint,
+    pk: PathBuf,
+    vk: PathBuf,
+}
+
+impl KeyStore {
+    /// Opens the store in `dir`, creating the directory if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, KeyStoreError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The proving key of circuits with `params` under `setup`, whose
+    /// digest is `digest`, generated and stored along with its verifying
+    /// key if the store has none.
+    pub fn proving_key<B: Backend>(
+        &self,
+        setup: &B::Setup,
+        digest: &SetupDigest,
+        params: BrainfuckParams,
+    ) -> Result<StoredKey<ProvingKey<B::Curve>>, KeyStoreError> {
+        let entry = self.entry::<B>(setup, digest, &params)?;
+        if let Some(key) = load(&entry.pk, &entry.fingerprint, read_pk::<B>)? {
+            return Ok(key);
+        }
+        generate::<B>(setup, params, &entry)
+    }
+
+    /// The verifying key of circuits with `params` under `setup`, whose
+    /// digest is `digest`, taken from the proving key if the store has none.
+    pub fn verifying_key<B: Backend>(
+        &self,
+        setup: &B::Setup,
+        digest: &SetupDigest,
+        params: BrainfuckParams,
+    ) -> Result<StoredKey<VerifyingKey<B::Curve>>, KeyStoreError> {
+        let entry = self.entry::<B>(setup, digest, &params)?;
+        if let Some(key) = load(&entry.vk, &entry.fingerprint, read_vk::<B>)? {
+            return Ok(key);
+        }
+        let pk = match load(&entry.pk, &entry.fingerprint, read_pk::<B>)? {
+            Some(pk) => {
+                let vk = pk.key.get_vk();
+                store(&entry.vk, |writer| write_vk::<B>(writer, &params, pk.k, vk))?;
+                pk
+            }
+            // stores the verifying key too
+            None => generate::<B>(setup, params, &entry)?,
+        };
+        Ok(StoredKey {
+            params,
+            k: pk.k,
+            fingerprint: pk.fingerprint,
+            key: pk.key.get_vk().clone(),
+        })
+    }
+
+    /// A prover with its proving key from the store.
+    pub fn prover<B: Backend>(
+        &self,
+        backend: B,
+        setup: B::Setup,
+        digest: &SetupDigest,
+        params: BrainfuckParams,
+    ) -> Result<Prover<B>, KeyStoreError> {
+        let key = self.proving_key::<B>(&setup, digest, params)?;
+        Ok(Prover::from_key(backend, setup, key)?)
+    }
+
+    fn entry<B: Backend>(
+        &self,
+        setup: &B::Setup,
+        digest: &SetupDigest,
+        params: &BrainfuckParams,
+    ) -> Result<Entry, KeyStoreError> {
+        let k = check_setup::<B>(setup, params)?;
+        let fingerprint = Fingerprint::new::<B>(params, k);
+        let stem = format!("{fingerprint}-{digest}");
+        Ok(Entry {
+            fingerprint,
+            pk: self.dir.join(format!("{stem}.pk")),
+            vk: self.dir.join(format!("{stem}.vk")),
+        })
+    }
+}
+
+/// Generates the keys of `entry` and stores both.
+fn generate<B: Backend>(
+    setup: &B::Setup,
+    params: BrainfuckParams,
+    entry: &Entry,
+) -> Result<StoredKey<ProvingKey<B::Curve>>, KeyStoreError> {
+    let pk = keygen::<B>(setup, params)?;
+    let k = B::k(setup);
+    store(&entry.pk, |writer| write_pk::<B>(writer, &params, k, &pk))?;
+    store(&entry.vk, |writer| {
+        write_vk::<B>(writer, &params, k, pk.get_vk())
+    })?;
+    Ok(StoredKey {
+        params,
+        k,
+        fingerprint: entry.fingerprint,
+        key: pk,
+    })
+}
+
+/// Loads the key at `path`, or `None` if there is no usable one.
+fn load<K>(
+    path: &Path,
+    expected: &Fingerprint,
+    read: impl FnOnce(&mut BufReader<File>) -> Result<StoredKey<K>, EnvelopeError>,
+) -> Result<Option<StoredKey<K>>, KeyStoreError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    match read(&mut BufReader::new(file)) {
+        Ok(key) if key.fingerprint == *expected => Ok(Some(key)),
+        // overwritten once the key is regenerated
+        _ => Ok(None),
+    }
+}
+
+/// Writes a key next to `path` and moves it into place, so that readers
+/// never see a partial key. Jobs generating the same key concurrently each
+/// write their own file, and the last one wins.
+fn store(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), EnvelopeError>,
+) -> Result<(), KeyStoreError> {
+    static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+    let mut name = path.file_name().unwrap().to_owned();
+    name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp = path.with_file_name(name);
+    let mut writer = BufWriter::new(File::create(&temp)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::halo2curves::pasta::Fp;
+
+    use super::*;
+    use crate::{
+        fixtures::{params, setup, CODE},
+        prover::{verify, Ipa},
+    };
+
+    fn temp_store(name: &str) -> KeyStore {
+        let dir = std::env::temp_dir().join(format!("bf-keys-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        KeyStore::open(dir).unwrap()
+    }
+
+    #[test]
+    fn test_keys_reused() {
+        let store = temp_store("reused");
+        let setup = setup::<Ipa>(&(), &params());
+        let digest = SetupDigest::new::<Ipa>(&setup).unwrap();
+        let prover = store.prover(Ipa, setup.clone(), &digest, params()).unwrap();
+        let entry = store.entry::<Ipa>(&setup, &digest, &params()).unwrap();
+        let written = fs::metadata(&entry.pk).unwrap().modified().unwrap();
+
+        // a missing verifying key is taken from the stored proving key
+        fs::remove_file(&entry.vk).unwrap();
+        let vk = store
+            .verifying_key::<Ipa>(&setup, &digest, params())
+            .unwrap();
+        assert!(entry.vk.exists());
+        let proof = prover.prove(&CODE, &[Fp::from(97)]).unwrap();
+        verify(&Ipa, &setup, &vk.key, &proof).unwrap();
+
+        let loaded = store.prover(Ipa, setup.clone(), &digest, params()).unwrap();
+        verify(
+            &Ipa,
+            &setup,
+            &vk.key,
+            &loaded.prove(&CODE, &[Fp::from(98)]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::metadata(&entry.pk).unwrap().modified().unwrap(),
+            written
+        );
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_stale_keys_regenerated() {
+        let store = temp_store("stale");
+        let setup = setup::<Ipa>(&(), &params());
+        let digest = SetupDigest::new::<Ipa>(&setup).unwrap();
+        let other = BrainfuckParams {
+            max_steps: 60,
+            ..params()
+        };
+        store.proving_key::<Ipa>(&setup, &digest, other).unwrap();
+        let stale = store.entry::<Ipa>(&setup, &digest, &other).unwrap();
+        let entry = store.entry::<Ipa>(&setup, &digest, &params()).unwrap();
+
+        // keys of another configuration, and truncated keys
+        fs::copy(&stale.pk, &entry.pk).unwrap();
+        fs::copy(&stale.vk, &entry.vk).unwrap();
+        let vk = store
+            .verifying_key::<Ipa>(&setup, &digest, params())
+            .unwrap();
+        assert_eq!(vk.fingerprint, entry.fingerprint);
+        let bytes = fs::read(&entry.pk).unwrap();
+        fs::write(&entry.pk, &bytes[..bytes.len() / 2]).unwrap();
+        let pk = store.proving_key::<Ipa>(&setup, &digest, params()).unwrap();
+        assert_eq!(pk.params, params());
+        assert_eq!(fs::read(&entry.pk).unwrap(), bytes);
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+}
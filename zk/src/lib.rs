@@ -1,7 +1,23 @@
 pub mod circuit;
+pub mod diagnostics;
+pub mod envelope;
+#[cfg(test)]
+mod fixtures;
 pub mod gadgets;
-pub mod input_table;
 pub mod instruction_table;
+pub mod io_table;
+pub mod key_store;
 pub mod memory_table;
-pub mod output_table;
+pub mod padding;
+pub mod params;
 pub mod processor_table;
+pub mod prover;
+pub mod public_inputs;
+#[cfg(test)]
+mod random_programs;
+pub mod receipt;
+pub mod setup;
+pub mod sizing;
+pub mod table;
+#[cfg(test)]
+mod tamper;
@@ -1,101 +1,265 @@
 use std::env::consts;
 
+use crate::{
+    gadgets::less_than::{LtChip, LtConfig},
+    table::{Annotations, Argument, Export, PublicCell, SlotTag, Table, TableContext, Witness},
+};
 use halo2_proofs::{
     arithmetic::Field,
-    circuit::Value,
-    halo2curves::bn256::Fr,
-    plonk::{Advice, Column, ConstraintSystem, Expression, Selector},
+    circuit::{Region, Value},
+    halo2curves::ff::PrimeField,
+    plonk::{Advice, Column, ConstraintSystem, ErrorFront, Expression, Selector},
     poly::Rotation,
 };
 use vm::{
     interpreter::{ADD, GETCHAR, LB, PUTCHAR, RB, SHL, SHR, SUB},
-    table::Tables,
+    table::{MemoryTableRow, Tables},
 };
-#[derive(Clone)]
 
-pub struct MemoryTableConfig {
+use crate::padding::{configure_enable, next_slot, transition};
+/// Columns of one logical row. A row holds `steps_per_row` of them, one per
+/// slot.
+#[derive(Clone, Copy)]
+pub struct MemorySlot<F: PrimeField> {
     pub clk: Column<Advice>,
     pub mp: Column<Advice>,
     pub mv: Column<Advice>,
-    pub s_m: Selector,
+    pub enable: Column<Advice>,
+    pub lt: LtConfig<F, 4>,
+    /// slot of the processor table holding this access, `clk % steps_per_row`
+    pub clk_tag: SlotTag,
 }
-pub struct MemoryTableChip {
-    config: MemoryTableConfig,
+#[derive(Clone)]
+
+pub struct MemoryTableConfig<F: PrimeField> {
+    pub slots: Vec<MemorySlot<F>>,
+    pub s_m: Selector,
+    pub q_row: Selector,
+    pub q_first: Selector,
 }
-impl MemoryTableChip {
-    pub fn construct(config: MemoryTableConfig) -> Self {
-        Self { config }
-    }
-    pub fn configure(meta: &mut ConstraintSystem<Fr>) -> MemoryTableConfig {
-        let clk = meta.advice_column();
-        let mp = meta.advice_column();
-        let mv = meta.advice_column();
+pub struct MemoryTable;
+impl<F: PrimeField> Table<F> for MemoryTable {
+    const NAME: &'static str = "memory";
+    type Config = MemoryTableConfig<F>;
+    type Row = MemoryTableRow<F>;
+
+    fn configure(meta: &mut ConstraintSystem<F>, ctx: &TableContext<'_>) -> MemoryTableConfig<F> {
+        let range_check = ctx.range_check;
+        let n_slots = ctx.params.steps_per_row;
         let s_m = meta.selector();
-        let ZERO = Expression::Constant(Fr::ZERO);
-        let ONE = Expression::Constant(Fr::ONE);
-        let TWO = Expression::Constant(Fr::from(2));
+        let q_row = meta.selector();
+        let q_first = meta.selector();
+        let enable = configure_enable(meta, q_row, s_m, n_slots);
+        let columns = enable
+            .into_iter()
+            .map(|enable| {
+                (
+                    meta.advice_column(),
+                    meta.advice_column(),
+                    meta.advice_column(),
+                    enable,
+                )
+            })
+            .collect::<Vec<_>>();
+        let ZERO = Expression::Constant(F::ZERO);
+        let ONE = Expression::Constant(F::ONE);
+        let TWO = Expression::Constant(F::from(2));
+
+        let mut slots = Vec::with_capacity(n_slots);
+        for slot in 0..n_slots {
+            let (clk, mp, mv, enable) = columns[slot];
+            let (next_index, rotation) = next_slot(slot, n_slots);
+            let (next_clk, next_mp, next_mv, next_enable) = columns[next_index];
+
+            meta.create_gate("memory table transition constraints", |meta| {
+                let cur_mp_cell = meta.query_advice(mp, Rotation::cur());
+                let next_mp_cell = meta.query_advice(next_mp, rotation);
+                let next_mv_cell = meta.query_advice(next_mv, rotation);
+                let cur_mv_cell = meta.query_advice(mv, Rotation::cur());
+                let cur_clk_cell = meta.query_advice(clk, Rotation::cur());
+                let next_clk_cell = meta.query_advice(next_clk, rotation);
+                let s_m_cell = transition(meta, slot, n_slots, q_row, s_m)
+                    * meta.query_advice(next_enable, rotation);
 
-        meta.create_gate("memory table transition constraints", |meta| {
-            let cur_mp_cell = meta.query_advice(mp, Rotation::cur());
-            let next_mp_cell = meta.query_advice(mp, Rotation::next());
-            let next_mv_cell = meta.query_advice(mv, Rotation::next());
-            let cur_mv_cell = meta.query_advice(mv, Rotation::cur());
-            let cur_clk_cell = meta.query_advice(clk, Rotation::cur());
-            let next_clk_cell = meta.query_advice(clk, Rotation::next());
-            let s_m_cell = meta.query_selector(s_m);
+                let constraint_m0 = (next_mp_cell.clone() - cur_mp_cell.clone() - ONE.clone())
+                    * (next_mp_cell.clone() - cur_mp_cell.clone());
+                let constraint_m1 = (next_mp_cell.clone() - cur_mp_cell.clone() - ONE.clone())
+                    * (next_mv_cell.clone() - cur_mv_cell)
+                    * (next_clk_cell - cur_clk_cell - ONE.clone());
+                let constraint_m2 = (next_mp_cell - cur_mp_cell) * next_mv_cell;
+                vec![
+                    s_m_cell.clone() * constraint_m0,
+                    s_m_cell.clone() * constraint_m1,
+                    s_m_cell * constraint_m2,
+                ]
+            });
 
-            let constraint_m0 = (next_mp_cell.clone() - cur_mp_cell.clone() - ONE.clone())
-                * (next_mp_cell.clone() - cur_mp_cell.clone());
-            let constraint_m1 = (next_mp_cell.clone() - cur_mp_cell.clone() - ONE.clone())
-                * (next_mv_cell.clone() - cur_mv_cell)
-                * (next_clk_cell - cur_clk_cell - ONE);
-            let constraint_m2 = (next_mp_cell - cur_mp_cell) * next_mv_cell;
+            // The table is sorted by (mp, clk): within one cell clk must increase.
+            let lt_config: LtConfig<F, 4> = LtChip::configure(
+                meta,
+                range_check,
+                |cell| {
+                    transition(cell, slot, n_slots, q_row, s_m)
+                        * cell.query_advice(next_enable, rotation)
+                },
+                |cell| cell.query_advice(clk, Rotation::cur()),
+                |cell| cell.query_advice(next_clk, rotation),
+            );
+            meta.create_gate("memory table clk order", |meta| {
+                let s_m_cell = transition(meta, slot, n_slots, q_row, s_m)
+                    * meta.query_advice(next_enable, rotation);
+                let cur_mp_cell = meta.query_advice(mp, Rotation::cur());
+                let next_mp_cell = meta.query_advice(next_mp, rotation);
+                let lt = meta.query_advice(lt_config.lt, Rotation::cur());
+                let same_cell = ONE.clone() - (next_mp_cell - cur_mp_cell);
+                vec![s_m_cell * same_cell * (lt - ONE.clone())]
+            });
+
+            slots.push(MemorySlot {
+                clk,
+                mp,
+                mv,
+                enable,
+                lt: lt_config,
+                clk_tag: SlotTag::configure(meta, q_row, n_slots),
+            });
+        }
+
+        //Boundary Constraints
+        let first = slots[0];
+        meta.create_gate("memory table boundary constraints", |meta| {
+            let q_first = meta.query_selector(q_first);
+            let clk_cell = meta.query_advice(first.clk, Rotation::cur());
+            let mp_cell = meta.query_advice(first.mp, Rotation::cur());
+            let mv_cell = meta.query_advice(first.mv, Rotation::cur());
+            let enable_cell = meta.query_advice(first.enable, Rotation::cur());
             vec![
-                s_m_cell.clone() * constraint_m0,
-                s_m_cell.clone() * constraint_m1,
-                s_m_cell * constraint_m2,
+                q_first.clone() * clk_cell,
+                q_first.clone() * mp_cell,
+                q_first.clone() * mv_cell,
+                q_first * (enable_cell - ONE.clone()),
             ]
         });
-        // meta.lookup_any("constraints: memory table is permutation of processor table", |meta|{
-        //     let
-        // })
 
-        MemoryTableConfig { clk, mp, mv, s_m }
+        MemoryTableConfig {
+            slots,
+            s_m,
+            q_row,
+            q_first,
+        }
+    }
+    fn exports(config: &MemoryTableConfig<F>) -> Vec<Export<F>> {
+        let slots = config.slots.clone();
+        vec![Export::new("memory.cells", slots.len(), move |meta, slot| {
+            let MemorySlot {
+                clk,
+                mp,
+                mv,
+                enable,
+                ..
+            } = slots[slot];
+            let enable = meta.query_advice(enable, Rotation::cur());
+            vec![
+                enable.clone() * meta.query_advice(clk, Rotation::cur()),
+                enable.clone() * meta.query_advice(mp, Rotation::cur()),
+                enable * meta.query_advice(mv, Rotation::cur()),
+            ]
+        })
+        .tagged({
+            let slots = config.slots.clone();
+            move |meta, slot| slots[slot].clk_tag.expr(meta)
+        })]
     }
-    pub fn assign(
-        &self,
-        mut layouter: impl halo2_proofs::circuit::Layouter<Fr>,
-        tables: &Tables,
-    ) -> Result<(), halo2_proofs::plonk::ErrorFront> {
-        layouter.assign_region(
-            || "memory table",
-            |mut region| {
-                for (offset, row) in tables.processor_table.iter().enumerate() {
-                    region.assign_advice(
-                        || "clk",
-                        self.config.clk,
-                        offset,
-                        || Value::known(Fr::from(row.clk)),
-                    )?;
-                    region.assign_advice(
-                        || "mp",
-                        self.config.mp,
-                        offset,
-                        || Value::known(Fr::from(row.mp as u64)),
-                    )?;
-                    region.assign_advice(
-                        || "mv",
-                        self.config.mv,
-                        offset,
-                        || Value::known(row.mv),
-                    )?;
-                    if offset != tables.memory_table.len() - 1 {
-                        region.enable_selector(|| "s_m", &self.config.s_m, offset)?;
-                    }
+    fn annotate(config: &MemoryTableConfig<F>, columns: &mut Annotations) {
+        for (slot, slot_columns) in config.slots.iter().enumerate() {
+            columns.column("clk", slot, slot_columns.clk);
+            columns.column("mp", slot, slot_columns.mp);
+            columns.column("mv", slot, slot_columns.mv);
+            columns.column("enable", slot, slot_columns.enable);
+            columns.lt("clk_lt", slot, &slot_columns.lt);
+            columns.tag("clk_tag", slot, &slot_columns.clk_tag);
+        }
+    }
+    /// Both tables hold distinct tuples: processor steps have distinct clks
+    /// and memory rows are sorted by (mp, clk) with no repeated clk within a
+    /// cell. Lookups both ways thus make them permutations of each other.
+    fn arguments() -> Vec<Argument> {
+        vec![
+            Argument::lookup(
+                "memory table: row is a processor step",
+                "memory.cells",
+                "processor.memory",
+            ),
+            Argument::lookup(
+                "memory table: processor step has a memory row",
+                "processor.memory",
+                "memory.cells",
+            ),
+        ]
+    }
+    fn rows(tables: &Tables<F>) -> &[MemoryTableRow<F>] {
+        &tables.memory_table
+    }
+    fn empty_row() -> MemoryTableRow<F> {
+        MemoryTableRow {
+            clk: 0,
+            mp: 0,
+            mv: F::ZERO,
+        }
+    }
+    /// Assigns the memory table, sorted by (mp, clk).
+    fn assign(
+        config: &MemoryTableConfig<F>,
+        region: &mut Region<'_, F>,
+        _witness: &Witness<'_, F>,
+        rows: &[(MemoryTableRow<F>, bool)],
+    ) -> Result<Vec<PublicCell<F>>, ErrorFront> {
+        let n_slots = config.slots.len();
+        let height = rows.len() / n_slots;
+        let lt_chips = config
+            .slots
+            .iter()
+            .map(|slot| LtChip::construct(slot.lt))
+            .collect::<Vec<_>>();
+        for (index, (row, enable)) in rows.iter().enumerate() {
+            let (offset, slot) = (index / n_slots, index % n_slots);
+            let columns = &config.slots[slot];
+            region.assign_advice(
+                || "clk",
+                columns.clk,
+                offset,
+                || Value::known(F::from(row.clk)),
+            )?;
+            region.assign_advice(
+                || "mp",
+                columns.mp,
+                offset,
+                || Value::known(F::from(row.mp as u64)),
+            )?;
+            region.assign_advice(|| "mv", columns.mv, offset, || Value::known(row.mv))?;
+            region.assign_advice(
+                || "enable",
+                columns.enable,
+                offset,
+                || Value::known(F::from(*enable as u64)),
+            )?;
+            columns
+                .clk_tag
+                .assign(region, offset, row.clk as usize % n_slots)?;
+            if let Some((next, _)) = rows.get(index + 1) {
+                lt_chips[slot].assign(region, offset, F::from(row.clk), F::from(next.clk))?;
+            }
+            if slot == 0 {
+                if offset == 0 {
+                    region.enable_selector(|| "q_first", &config.q_first, offset)?;
+                }
+                region.enable_selector(|| "q_row", &config.q_row, offset)?;
+                if offset != height - 1 {
+                    region.enable_selector(|| "s_m", &config.s_m, offset)?;
                 }
+            }
+        }
 
-                Ok(())
-            },
-        )
+        Ok(vec![])
     }
 }
@@ -0,0 +1,97 @@
+use halo2_proofs::{
+    arithmetic::Field,
+    plonk::{Advice, Column, ConstraintSystem, ErrorFront, Expression, Selector, VirtualCells},
+    poly::Rotation,
+};
+
+/// Tables hold `slots` logical rows per physical row, so logical row `r` sits
+/// in slot `r % slots` of physical row `r / slots`. Returns the slot holding
+/// the logical row after one in `slot`, and the rotation that reaches it.
+pub fn next_slot(slot: usize, slots: usize) -> (usize, Rotation) {
+    if slot + 1 < slots {
+        (slot + 1, Rotation::cur())
+    } else {
+        (0, Rotation::next())
+    }
+}
+
+/// Selector of the transition out of `slot`: the next logical row is in the
+/// same physical row for every slot but the last, which moves on to the next
+/// physical row.
+pub fn transition<F: Field>(
+    meta: &mut VirtualCells<F>,
+    slot: usize,
+    slots: usize,
+    q_row: Selector,
+    q_trans: Selector,
+) -> Expression<F> {
+    if slot + 1 < slots {
+        meta.query_selector(q_row)
+    } else {
+        meta.query_selector(q_trans)
+    }
+}
+
+/// Allocates a table's enable flag in each of its `slots`: 1 on the logical
+/// rows holding trace data and 0 on the padding rows after them.
+///
+/// The flag is an advice column rather than a selector so that trace lengths
+/// stay out of the verifying key. `q_row` must be enabled on every row of the
+/// padded table and `q_trans` on every row but the last.
+pub fn configure_enable<F: Field>(
+    meta: &mut ConstraintSystem<F>,
+    q_row: Selector,
+    q_trans: Selector,
+    slots: usize,
+) -> Vec<Column<Advice>> {
+    let enable = (0..slots).map(|_| meta.advice_column()).collect::<Vec<_>>();
+    for slot in 0..slots {
+        let (next, rotation) = next_slot(slot, slots);
+        let (cur_enable, next_enable) = (enable[slot], enable[next]);
+        meta.create_gate("enable flag", |meta| {
+            let q_next = transition(meta, slot, slots, q_row, q_trans);
+            let q_row = meta.query_selector(q_row);
+            let cur_enable = meta.query_advice(cur_enable, Rotation::cur());
+            let next_enable = meta.query_advice(next_enable, rotation);
+            let one = Expression::Constant(F::ONE);
+            vec![
+                q_row * cur_enable.clone() * (one.clone() - cur_enable.clone()),
+                // padding rows only ever follow padding rows
+                q_next * next_enable * (one - cur_enable),
+            ]
+        });
+    }
+    enable
+}
+
+/// Logical rows of trace data a table of `height` physical rows holds: all
+/// but the last physical row, which is kept for padding.
+pub fn capacity(height: usize, slots: usize) -> usize {
+    height.saturating_sub(1) * slots
+}
+
+/// Pads `rows` to `height` physical rows of `slots` logical rows each, with
+/// copies of the last row (or `empty()` when there is none), pairing every
+/// row with its enable flag.
+///
+/// The last physical row must be padding only, so that every slot of every
+/// table contributes an all-zero tuple to the lookups gated by its enable
+/// flag.
+pub fn padded<T: Clone>(
+    rows: &[T],
+    height: usize,
+    slots: usize,
+    empty: impl FnOnce() -> T,
+) -> Result<Vec<(T, bool)>, ErrorFront> {
+    if height == 0 || rows.len() > capacity(height, slots) {
+        return Err(ErrorFront::Synthesis);
+    }
+    let padding = rows.last().cloned().unwrap_or_else(empty);
+    Ok(rows
+        .iter()
+        .cloned()
+        .map(|row| (row, true))
+        .chain(std::iter::repeat((padding, false)))
+        .take(height * slots)
+        .collect())
+}
@@ -0,0 +1,135 @@
+use vm::{
+    config::VmConfig,
+    interpreter::{GETCHAR, MAX_PROGRAM_LEN, PUTCHAR, TAPE_SIZE},
+};
+
+use crate::{gadgets::range_check::RANGE_CHECK_BITS, processor_table::CI_DOMAIN, sizing::MAX_K};
+
+/// How a program's input and output appear in the proof.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IoMode {
+    /// Input and output values are public inputs.
+    #[default]
+    Public,
+    /// Input values stay private, output values are public inputs.
+    PrivateInput,
+    /// The program performs no I/O: `,` and `.` are rejected and the input
+    /// and output tables are not built.
+    None,
+}
+
+/// Machine and layout parameters of a `BrainfuckCircuit`. Each distinct value
+/// yields its own constraint system, and so its own verifying key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BrainfuckParams {
+    /// Width of a memory cell in bits, with `+` and `-` wrapping around.
+    /// `None` keeps cells as unbounded field elements.
+    pub cell_bits: Option<u32>,
+    /// Number of memory cells on the tape.
+    pub tape_size: usize,
+    /// Longest compiled program, jump operands included.
+    pub max_program_len: usize,
+    /// Most steps a proven execution may take, the halt row excluded.
+    pub max_steps: usize,
+    pub io_mode: IoMode,
+    /// Logical rows (VM steps, in the processor table) packed into each row
+    /// of every table. Packing trades columns, and lookups between tables,
+    /// for height.
+    pub steps_per_row: usize,
+    /// Expose the clk of the halt row as a public input.
+    pub expose_final_clk: bool,
+    /// Expose the compiled program as a public input, so that proofs
+    /// identify the program they ran. Otherwise only its length is public.
+    pub expose_program: bool,
+}
+
+impl Default for BrainfuckParams {
+    fn default() -> Self {
+        Self {
+            cell_bits: None,
+            tape_size: TAPE_SIZE,
+            max_program_len: MAX_PROGRAM_LEN,
+            max_steps: 128,
+            io_mode: IoMode::Public,
+            steps_per_row: 1,
+            expose_final_clk: false,
+            expose_program: false,
+        }
+    }
+}
+
+impl BrainfuckParams {
+    /// The interpreter configuration producing traces this circuit accepts.
+    pub fn vm_config(&self) -> VmConfig {
+        VmConfig {
+            tape_size: self.tape_size,
+            cell_bits: self.cell_bits,
+        }
+    }
+
+    /// Rows every table is padded to. The processor and memory tables hold
+    /// one logical row per step plus the halt row, the instruction table one
+    /// per program position plus the halt position; then comes the padding
+    /// row every table needs.
+    pub fn height(&self) -> usize {
+        let logical_rows = (self.max_steps + 1).max(self.max_program_len + 1);
+        logical_rows.div_ceil(self.steps_per_row) + 1
+    }
+
+    /// Bit width of the shared range table: wide enough to bound ip, mp and
+    /// memory cells with a single lookup each.
+    pub fn range_bits(&self) -> usize {
+        let bits_for = |max: usize| (usize::BITS - max.leading_zeros()) as usize;
+        [
+            RANGE_CHECK_BITS,
+            bits_for(self.max_program_len),
+            bits_for(self.tape_size - 1),
+            self.cell_bits.unwrap_or(0) as usize,
+        ]
+        .into_iter()
+        .max()
+        .unwrap()
+    }
+
+    /// Values `ci` may hold under this configuration.
+    pub fn ci_domain(&self) -> Vec<u8> {
+        CI_DOMAIN
+            .into_iter()
+            .filter(|&ci| self.io_mode != IoMode::None || (ci != GETCHAR && ci != PUTCHAR))
+            .collect()
+    }
+
+    pub fn has_io(&self) -> bool {
+        self.io_mode != IoMode::None
+    }
+
+    /// Panics on parameters `check` rejects.
+    pub(crate) fn validate(&self) {
+        if let Err(what) = self.check() {
+            panic!("{what}");
+        }
+    }
+
+    /// Rejects parameters no circuit can be configured with.
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.max_program_len > MAX_PROGRAM_LEN {
+            return Err("programs that long cannot encode their jump targets");
+        }
+        if self.tape_size == 0 {
+            return Err("the tape needs at least one cell");
+        }
+        // the processor table or the range table alone would exceed the
+        // largest circuit
+        if self.max_steps >= 1 << MAX_K {
+            return Err("too many steps for the largest circuit");
+        }
+        // rows beyond the steps and the halt row would only hold padding
+        if self.steps_per_row == 0 || self.steps_per_row > self.max_steps + 1 {
+            return Err("every row holds at least one step and at most all of them");
+        }
+        if self.range_bits() >= MAX_K as usize {
+            return Err("cells too wide for the range table");
+        }
+        Ok(())
+    }
+}
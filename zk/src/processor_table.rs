@@ -1,20 +1,33 @@
 use halo2_proofs::{
     arithmetic::Field,
-    circuit::Value,
-    halo2curves::bn256::Fr,
-    plonk::{Advice, Column, ConstraintSystem, Expression, Selector},
+    circuit::{Region, Value},
+    halo2curves::ff::PrimeField,
+    plonk::{
+        Advice, Column, ConstraintSystem, ErrorFront, Expression, Selector, TableColumn,
+        VirtualCells,
+    },
     poly::Rotation,
 };
 use vm::{
     interpreter::{ADD, GETCHAR, LB, PUTCHAR, RB, SHL, SHR, SUB},
-    table::Tables,
+    register::Registers,
+    table::{ProcessTableRow, Tables},
 };
-pub struct ProcessTableChip {
-    config: ProcessorTableConfig,
-}
-#[derive(Clone)]
 
-pub struct ProcessorTableConfig {
+use crate::{
+    circuit::{FINAL_CLK_ROW, PROGRAM_LEN_ROW},
+    gadgets::is_zero::{IsZeroChip, IsZeroConfig},
+    padding::{configure_enable, next_slot, transition},
+    table::{Annotations, Export, FixedTable, PublicCell, SlotTag, Table, TableContext, Witness},
+};
+/// Every value `ci` can hold: the eight opcodes plus the `0` written to the
+/// final (halt) row by the interpreter.
+pub(crate) const CI_DOMAIN: [u8; 9] = [ADD, SUB, SHL, SHR, GETCHAR, PUTCHAR, LB, RB, 0];
+
+pub struct ProcessorTable;
+/// Columns of one step. A row holds `steps_per_row` steps, one per slot.
+#[derive(Clone)]
+pub struct ProcessorStep<F: PrimeField> {
     pub clk: Column<Advice>,
     pub ip: Column<Advice>,
     pub ci: Column<Advice>,
@@ -22,219 +35,565 @@ pub struct ProcessorTableConfig {
     pub mp: Column<Advice>,
     pub mv: Column<Advice>,
     pub mvi: Column<Advice>,
+    pub mv_is_zero: IsZeroConfig<F>,
+    pub enable: Column<Advice>,
+    /// slot of the memory table holding this step
+    pub memory_tag: SlotTag,
+    /// slot of the instruction table holding `ip`
+    pub ip_tag: SlotTag,
+    /// slot of the input or output table holding the value read or written
+    pub io_tag: SlotTag,
+}
+#[derive(Clone)]
+
+pub struct ProcessorTableConfig<F: PrimeField> {
+    pub steps: Vec<ProcessorStep<F>>,
     pub s_b: Selector,
     pub s_c: Selector,
     pub s_p: Selector,
+    pub s_last: Selector,
+    /// values `ci` may hold, see `BrainfuckParams::ci_domain`
+    pub domain: Vec<u8>,
+    /// fixed table of the values in `domain`
+    pub decode: TableColumn,
+    pub expose_final_clk: bool,
 }
-impl ProcessTableChip {
-    pub fn construct(config: ProcessorTableConfig) -> Self {
-        Self { config }
-    }
-    pub fn configure(meta: &mut ConstraintSystem<Fr>) -> ProcessorTableConfig {
-        let clk = meta.advice_column();
-        let ip = meta.advice_column();
-        let ci = meta.advice_column();
-        let ni = meta.advice_column();
-        let mp = meta.advice_column();
-        let mv = meta.advice_column();
-        let mvi = meta.advice_column();
+impl<F: PrimeField> Table<F> for ProcessorTable {
+    const NAME: &'static str = "processor";
+    type Config = ProcessorTableConfig<F>;
+    type Row = ProcessTableRow<F>;
+
+    fn configure(
+        meta: &mut ConstraintSystem<F>,
+        ctx: &TableContext<'_>,
+    ) -> ProcessorTableConfig<F> {
+        let TableContext {
+            range_check,
+            params,
+        } = *ctx;
+        let slots = params.steps_per_row;
         let s_b = meta.selector();
         let s_c = meta.selector();
         let s_p = meta.selector();
+        let s_last = meta.selector();
+        let enable = configure_enable(meta, s_c, s_p, slots);
+        let decode = meta.lookup_table_column();
+        meta.annotate_lookup_column(decode, || "LOOKUP_decode");
+
+        let domain = params.ci_domain();
+        let max_ip = params.max_program_len as u64;
+        let max_mp = params.tape_size as u64 - 1;
+        assert!(max_ip < range_check.size() as u64 && max_mp < range_check.size() as u64);
+        let cell_modulus = params.cell_bits.map(|bits| {
+            assert!(bits as usize <= range_check.bits);
+            F::from(2).pow_vartime([bits as u64])
+        });
+
+        let mut steps = Vec::with_capacity(slots);
+        for enable in enable {
+            let clk = meta.advice_column();
+            let ip = meta.advice_column();
+            let ci = meta.advice_column();
+            let ni = meta.advice_column();
+            let mp = meta.advice_column();
+            let mv = meta.advice_column();
+            let mvi = meta.advice_column();
+            meta.enable_equality(ip);
+            meta.enable_equality(clk);
+
+            // The deselectors below are only sound when ci is in their domain.
+            meta.lookup("ci is a valid opcode", |meta| {
+                vec![(meta.query_advice(ci, Rotation::cur()), decode)]
+            });
+
+            // 0 <= ip <= max_program_len and 0 <= mp < tape_size, as both the
+            // value and its distance to the bound must be in the range table.
+            range_check.register(meta, "ip range check", |meta| {
+                meta.query_advice(ip, Rotation::cur())
+            });
+            range_check.register(meta, "ip upper bound", |meta| {
+                Expression::Constant(F::from(max_ip)) - meta.query_advice(ip, Rotation::cur())
+            });
+            range_check.register(meta, "mp range check", |meta| {
+                meta.query_advice(mp, Rotation::cur())
+            });
+            range_check.register(meta, "mp upper bound", |meta| {
+                Expression::Constant(F::from(max_mp)) - meta.query_advice(mp, Rotation::cur())
+            });
 
-        let instructions = [ADD, SUB, SHL, SHR, GETCHAR, PUTCHAR, LB, RB];
+            // 0 <= mv < 2^cell_bits; `+` and `-` then wrap around at the bounds.
+            if let Some(modulus) = cell_modulus {
+                range_check.register(meta, "mv range check", |meta| {
+                    meta.query_advice(mv, Rotation::cur())
+                });
+                range_check.register(meta, "mv upper bound", |meta| {
+                    Expression::Constant(modulus - F::ONE) - meta.query_advice(mv, Rotation::cur())
+                });
+            }
 
-        let ZERO = Expression::Constant(Fr::ZERO);
-        let ONE = Expression::Constant(Fr::ONE);
-        let TWO = Expression::Constant(Fr::from(2));
+            //Consistency Constraints
+            let mv_is_zero = IsZeroChip::configure(
+                meta,
+                |meta| meta.query_selector(s_c),
+                |meta| meta.query_advice(mv, Rotation::cur()),
+                mvi,
+            );
+
+            steps.push(ProcessorStep {
+                clk,
+                ip,
+                ci,
+                ni,
+                mp,
+                mv,
+                mvi,
+                mv_is_zero,
+                enable,
+                memory_tag: SlotTag::configure(meta, s_c, slots),
+                ip_tag: SlotTag::configure(meta, s_c, slots),
+                io_tag: SlotTag::configure(meta, s_c, slots),
+            });
+        }
+
+        let instructions = domain
+            .iter()
+            .copied()
+            .filter(|&ci| ci != 0)
+            .collect::<Vec<u8>>();
+
+        let ZERO = Expression::Constant(F::ZERO);
+        let ONE = Expression::Constant(F::ONE);
+        let TWO = Expression::Constant(F::from(2));
 
         //Boundary Constraints
+        let first = &steps[0];
         meta.create_gate("boundary constraints", |meta| {
-            let clk_cell = meta.query_advice(clk, Rotation::cur());
-            let ip_cell = meta.query_advice(ip, Rotation::cur());
-            let mp_cell = meta.query_advice(mp, Rotation::cur());
-            let mv_cell = meta.query_advice(mv, Rotation::cur());
+            let clk_cell = meta.query_advice(first.clk, Rotation::cur());
+            let ip_cell = meta.query_advice(first.ip, Rotation::cur());
+            let mp_cell = meta.query_advice(first.mp, Rotation::cur());
+            let mv_cell = meta.query_advice(first.mv, Rotation::cur());
+            let enable_cell = meta.query_advice(first.enable, Rotation::cur());
             let s = meta.query_selector(s_b);
             vec![
                 s.clone() * clk_cell,
                 s.clone() * ip_cell,
                 s.clone() * mp_cell,
-                s * mv_cell,
+                s.clone() * mv_cell,
+                s * (enable_cell - ONE.clone()),
             ]
         });
 
-        //Consistency Constraints
-        meta.create_gate("Consistency constraints", |meta| {
-            let mv_cell = meta.query_advice(mv, Rotation::cur());
-            let mvi_cell = meta.query_advice(mvi, Rotation::cur());
+        for slot in 0..slots {
+            let (next_index, rotation) = next_slot(slot, slots);
+            let (cur, next) = (&steps[slot], &steps[next_index]);
 
-            let s = meta.query_selector(s_c);
-            vec![
-                s.clone() * mv_cell.clone() * (mv_cell.clone() * mvi_cell.clone() - ONE.clone()),
-                s * mvi_cell.clone() * (mv_cell * mvi_cell - ONE.clone()),
-            ]
-        });
+            //transition Constraints
+            meta.create_gate("processor table transition constraints", |meta| {
+                // transitions only hold between two rows of the trace
+                let s_p_cell = transition(meta, slot, slots, s_c, s_p)
+                    * meta.query_advice(next.enable, rotation);
+                let cur_ip_cell = meta.query_advice(cur.ip, Rotation::cur());
+                let next_ip_cell = meta.query_advice(next.ip, rotation);
+                let mv_is_zero_cell = cur.mv_is_zero.expr();
+                let cur_ni_cell = meta.query_advice(cur.ni, Rotation::cur());
+                let cur_mp_cell = meta.query_advice(cur.mp, Rotation::cur());
+                let next_mp_cell = meta.query_advice(next.mp, rotation);
+                let next_mv_cell = meta.query_advice(next.mv, rotation);
+                let cur_mv_cell = meta.query_advice(cur.mv, Rotation::cur());
+                let cur_clk_cell = meta.query_advice(cur.clk, Rotation::cur());
+                let next_clk_cell = meta.query_advice(next.clk, rotation);
+                let cur_ci_cell = meta.query_advice(cur.ci, Rotation::cur());
 
-        //transition Constraints
-        meta.create_gate("procerssor table transition constraints", |meta| {
-            let s_p_cell = meta.query_selector(s_p);
-            let cur_ip_cell = meta.query_advice(ip, Rotation::cur());
-            let next_ip_cell = meta.query_advice(ip, Rotation::next());
-            let cur_mvi_cell = meta.query_advice(mvi, Rotation::cur());
-            let cur_ni_cell = meta.query_advice(ni, Rotation::cur());
-            let cur_mp_cell = meta.query_advice(mp, Rotation::cur());
-            let next_mp_cell = meta.query_advice(mp, Rotation::next());
-            let next_mv_cell = meta.query_advice(mv, Rotation::next());
-            let cur_mv_cell = meta.query_advice(mv, Rotation::cur());
-            let cur_clk_cell = meta.query_advice(clk, Rotation::cur());
-            let next_clk_cell = meta.query_advice(clk, Rotation::next());
-
-            let constraint_p1 = instructions
-                .iter()
-                .map(|&x| {
-                    let deselector = create_deselector(x, &instructions);
-                    deselector
-                        * match x {
-                            LB => {
-                                cur_mv_cell.clone()
-                                    * (next_ip_cell.clone() - cur_ip_cell.clone() - TWO.clone())
-                                    + (cur_mv_cell.clone() * cur_mvi_cell.clone() - ONE.clone())
-                                        * (next_ip_cell.clone() - cur_ni_cell.clone())
+                let constraint_p1 = instructions
+                    .iter()
+                    .map(|&x| {
+                        let deselector = create_deselector(cur_ci_cell.clone(), x, &domain);
+                        deselector
+                            * match x {
+                                LB => {
+                                    (ONE.clone() - mv_is_zero_cell.clone())
+                                        * (next_ip_cell.clone() - cur_ip_cell.clone() - TWO.clone())
+                                        + mv_is_zero_cell.clone()
+                                            * (next_ip_cell.clone() - cur_ni_cell.clone())
+                                }
+
+                                RB => {
+                                    mv_is_zero_cell.clone()
+                                        * (next_ip_cell.clone() - cur_ip_cell.clone() - TWO.clone())
+                                        + (ONE.clone() - mv_is_zero_cell.clone())
+                                            * (next_ip_cell.clone() - cur_ni_cell.clone())
+                                }
+
+                                _ => next_ip_cell.clone() - cur_ip_cell.clone() - ONE.clone(),
                             }
+                    })
+                    .fold(ZERO.clone(), |acc, cur| acc + cur);
 
-                            RB => {
-                                (cur_mv_cell.clone() * cur_mvi_cell.clone() - ONE.clone())
-                                    * (next_ip_cell.clone() - cur_ip_cell.clone() - TWO.clone())
-                                    + cur_mv_cell.clone()
-                                        * (next_ip_cell.clone() - cur_ni_cell.clone())
+                let constraint_p2 = instructions
+                    .iter()
+                    .map(|&x| {
+                        let deselector = create_deselector(cur_ci_cell.clone(), x, &domain);
+                        deselector
+                            * match x {
+                                SHR => next_mp_cell.clone() - cur_mp_cell.clone() - ONE.clone(),
+                                SHL => next_mp_cell.clone() - cur_mp_cell.clone() + ONE.clone(),
+                                _ => next_mp_cell.clone() - cur_mp_cell.clone(),
                             }
+                    })
+                    .fold(ZERO.clone(), |acc, cur| acc + cur);
 
-                            _ => next_ip_cell.clone() - cur_ip_cell.clone() - ONE.clone(),
-                        }
-                })
-                .fold(ZERO.clone(), |acc, cur| acc + cur);
+                let constraint_p3 = instructions
+                    .iter()
+                    .map(|&x| {
+                        let deselector = create_deselector(cur_ci_cell.clone(), x, &domain);
+                        deselector
+                            * match x {
+                                ADD => {
+                                    let delta =
+                                        next_mv_cell.clone() - cur_mv_cell.clone() - ONE.clone();
+                                    match cell_modulus {
+                                        // 2^cell_bits - 1 wraps to 0
+                                        Some(modulus) => {
+                                            delta.clone() * (delta + Expression::Constant(modulus))
+                                        }
+                                        None => delta,
+                                    }
+                                }
+                                SUB => {
+                                    let delta =
+                                        next_mv_cell.clone() - cur_mv_cell.clone() + ONE.clone();
+                                    match cell_modulus {
+                                        // 0 wraps to 2^cell_bits - 1
+                                        Some(modulus) => {
+                                            delta.clone() * (delta - Expression::Constant(modulus))
+                                        }
+                                        None => delta,
+                                    }
+                                }
+                                SHR | SHL => ZERO.clone(),
+                                // the value read is bound to the input table by lookup
+                                GETCHAR => ZERO.clone(),
+                                LB | RB | PUTCHAR => next_mv_cell.clone() - cur_mv_cell.clone(),
+                                _ => unreachable!(),
+                            }
+                    })
+                    .fold(ZERO.clone(), |acc, cur| acc + cur);
+                vec![
+                    s_p_cell.clone() * (next_clk_cell - cur_clk_cell - ONE.clone()),
+                    s_p_cell.clone() * constraint_p1,
+                    s_p_cell.clone() * constraint_p2,
+                    s_p_cell * constraint_p3,
+                ]
+            });
 
-            let constraint_p2 = instructions
-                .iter()
-                .map(|&x| {
-                    let deselector = create_deselector(x, &instructions);
-                    deselector
-                        * match x {
-                            SHR => next_mp_cell.clone() - cur_mp_cell.clone() - ONE.clone(),
-                            SHL => next_mp_cell.clone() - cur_mp_cell.clone() + ONE.clone(),
-                            _ => next_mp_cell.clone() - cur_mp_cell.clone(),
-                        }
-                })
-                .fold(ZERO.clone(), |acc, cur| acc + cur);
+            //Terminal Constraints
+            meta.create_gate("processor table terminal constraints", |meta| {
+                let s_p_cell = transition(meta, slot, slots, s_c, s_p);
+                let next_enable_cell = meta.query_advice(next.enable, rotation);
+                let cur_clk_cell = meta.query_advice(cur.clk, Rotation::cur());
+                let next_clk_cell = meta.query_advice(next.clk, rotation);
+                let cur_ip_cell = meta.query_advice(cur.ip, Rotation::cur());
+                let next_ip_cell = meta.query_advice(next.ip, rotation);
+                let cur_ci_cell = meta.query_advice(cur.ci, Rotation::cur());
+                let next_ci_cell = meta.query_advice(next.ci, rotation);
+                let is_halt = create_deselector(cur_ci_cell.clone(), 0, &domain);
+                // padding rows repeat the row before them
+                let is_padding = s_p_cell.clone() * (ONE.clone() - next_enable_cell.clone());
+                vec![
+                    // a row followed by another step is not a halt row
+                    s_p_cell * next_enable_cell * is_halt,
+                    is_padding.clone() * (next_clk_cell - cur_clk_cell),
+                    is_padding.clone() * (next_ip_cell - cur_ip_cell),
+                    is_padding * (next_ci_cell - cur_ci_cell),
+                ]
+            });
+        }
 
-            let constraint_p3 = instructions
-                .iter()
-                .map(|&x| {
-                    let deselector = create_deselector(x, &instructions);
-                    deselector
-                        * match x {
-                            ADD => next_mv_cell.clone() - cur_mv_cell.clone() - ONE.clone(),
-                            SUB => next_mv_cell.clone() - cur_mv_cell.clone() + ONE.clone(),
-                            SHR | SHL | GETCHAR => ZERO.clone(),
-                            LB | RB | PUTCHAR => next_mv_cell.clone() - cur_mv_cell.clone(),
-                            _ => unreachable!(),
-                        }
-                })
-                .fold(ZERO.clone(), |acc, cur| acc + cur);
-            vec![
-                s_p_cell.clone() * (next_clk_cell - cur_clk_cell - ONE),
-                s_p_cell.clone() * constraint_p1,
-                s_p_cell.clone() * constraint_p2,
-                s_p_cell * constraint_p3,
-            ]
+        // so the last row holds the halt state
+        let last = &steps[slots - 1];
+        meta.create_gate("processor table halt row", |meta| {
+            let s_last_cell = meta.query_selector(s_last);
+            let cur_ci_cell = meta.query_advice(last.ci, Rotation::cur());
+            vec![s_last_cell * cur_ci_cell]
         });
 
         ProcessorTableConfig {
-            clk,
-            ip,
-            ci,
-            ni,
-            mp,
-            mv,
-            mvi,
+            steps,
             s_b,
             s_c,
             s_p,
+            s_last,
+            domain,
+            decode,
+            expose_final_clk: params.expose_final_clk,
         }
     }
-    pub fn assign(
-        &self,
-        mut layouter: impl halo2_proofs::circuit::Layouter<Fr>,
-        tables: &Tables,
-    ) -> Result<(), halo2_proofs::plonk::ErrorFront> {
-        layouter.assign_region(
-            || "processor table",
-            |mut region| {
-                for (offset, row) in tables.processor_table.iter().enumerate() {
-                    region.assign_advice(
-                        || "clk",
-                        self.config.clk,
-                        offset,
-                        || Value::known(Fr::from(row.clk)),
-                    )?;
-                    region.assign_advice(
-                        || "ip",
-                        self.config.ip,
-                        offset,
-                        || Value::known(Fr::from(row.ip as u64)),
-                    )?;
-                    region.assign_advice(
-                        || "ci",
-                        self.config.ci,
-                        offset,
-                        || Value::known(Fr::from(row.ci as u64)),
-                    )?;
-                    region.assign_advice(
-                        || "ni",
-                        self.config.ni,
-                        offset,
-                        || Value::known(Fr::from(row.ni as u64)),
-                    )?;
-                    region.assign_advice(
-                        || "mp",
-                        self.config.mp,
-                        offset,
-                        || Value::known(Fr::from(row.mp as u64)),
-                    )?;
-                    region.assign_advice(
-                        || "mv",
-                        self.config.mv,
-                        offset,
-                        || Value::known(row.mv),
-                    )?;
-                    region.assign_advice(
-                        || "ip",
-                        self.config.ip,
-                        offset,
-                        || Value::known(Fr::from(row.ip as u64)),
-                    )?;
-                    if offset == 0 {
-                        region.enable_selector(|| "s_b", &self.config.s_b, offset)?;
-                    }
-                    region.enable_selector(|| "s_c", &self.config.s_c, offset)?;
-                    if offset != tables.processor_table.len() - 1 {
-                        region.enable_selector(|| "s_p", &self.config.s_p, offset)?;
+    fn exports(config: &ProcessorTableConfig<F>) -> Vec<Export<F>> {
+        let slots = config.steps.len();
+        let steps = config.steps.clone();
+        let memory = Export::new("processor.memory", slots, {
+            let steps = steps.clone();
+            move |meta, slot| {
+                let step = &steps[slot];
+                let enable = meta.query_advice(step.enable, Rotation::cur());
+                vec![
+                    enable.clone() * meta.query_advice(step.clk, Rotation::cur()),
+                    enable.clone() * meta.query_advice(step.mp, Rotation::cur()),
+                    enable * meta.query_advice(step.mv, Rotation::cur()),
+                ]
+            }
+        })
+        .tagged({
+            let steps = steps.clone();
+            move |meta, slot| steps[slot].memory_tag.expr(meta)
+        });
+        let instruction = Export::new("processor.instruction", slots, {
+            let steps = steps.clone();
+            move |meta, slot| {
+                let step = &steps[slot];
+                let enable = meta.query_advice(step.enable, Rotation::cur());
+                vec![
+                    enable.clone() * meta.query_advice(step.ip, Rotation::cur()),
+                    enable.clone() * meta.query_advice(step.ci, Rotation::cur()),
+                    enable * meta.query_advice(step.ni, Rotation::cur()),
+                ]
+            }
+        })
+        .tagged({
+            let steps = steps.clone();
+            move |meta, slot| steps[slot].ip_tag.expr(meta)
+        });
+        let mut exports = vec![memory, instruction];
+
+        for (io, all_steps, io_steps) in [
+            (GETCHAR, "processor.getchar", "processor.getchar_steps"),
+            (PUTCHAR, "processor.putchar", "processor.putchar_steps"),
+        ] {
+            if !config.domain.contains(&io) {
+                continue;
+            }
+            // (clk, ci, value) of every step
+            exports.push(Export::new(all_steps, slots, {
+                let steps = steps.clone();
+                move |meta, slot| {
+                    let step = &steps[slot];
+                    let enable = meta.query_advice(step.enable, Rotation::cur());
+                    vec![
+                        enable.clone() * meta.query_advice(step.clk, Rotation::cur()),
+                        enable.clone() * meta.query_advice(step.ci, Rotation::cur()),
+                        enable * io_value(meta, &steps, slot, io),
+                    ]
+                }
+            }));
+            // (1, clk, value) on the GETCHAR or PUTCHAR steps only
+            let domain = config.domain.clone();
+            exports.push(
+                Export::new(io_steps, slots, {
+                    let steps = steps.clone();
+                    move |meta, slot| {
+                        let step = &steps[slot];
+                        let enable = meta.query_advice(step.enable, Rotation::cur());
+                        let ci = meta.query_advice(step.ci, Rotation::cur());
+                        let is_io = enable * create_deselector(ci, io, &domain);
+                        vec![
+                            is_io.clone(),
+                            is_io.clone() * meta.query_advice(step.clk, Rotation::cur()),
+                            is_io * io_value(meta, &steps, slot, io),
+                        ]
                     }
+                })
+                .tagged({
+                    let steps = steps.clone();
+                    move |meta, slot| steps[slot].io_tag.expr(meta)
+                }),
+            );
+        }
+        exports
+    }
+    fn annotate(config: &ProcessorTableConfig<F>, columns: &mut Annotations) {
+        for (slot, step) in config.steps.iter().enumerate() {
+            columns.column("clk", slot, step.clk);
+            columns.column("ip", slot, step.ip);
+            columns.column("ci", slot, step.ci);
+            columns.column("ni", slot, step.ni);
+            columns.column("mp", slot, step.mp);
+            columns.column("mv", slot, step.mv);
+            columns.column("mvi", slot, step.mvi);
+            columns.column("enable", slot, step.enable);
+            columns.tag("memory_tag", slot, &step.memory_tag);
+            columns.tag("ip_tag", slot, &step.ip_tag);
+            columns.tag("io_tag", slot, &step.io_tag);
+        }
+    }
+    fn fixed_tables(config: &ProcessorTableConfig<F>) -> Vec<FixedTable<F>> {
+        vec![FixedTable {
+            name: "decode table",
+            column: config.decode,
+            values: config
+                .domain
+                .iter()
+                .map(|&opcode| F::from(opcode as u64))
+                .collect(),
+        }]
+    }
+    fn rows(tables: &Tables<F>) -> &[ProcessTableRow<F>] {
+        &tables.processor_table
+    }
+    fn empty_row() -> ProcessTableRow<F> {
+        ProcessTableRow::from(Registers::default())
+    }
+    /// Padding rows copy the final (halt) row, so the last row's ip and clk
+    /// are the program length and the final clk.
+    fn assign(
+        config: &ProcessorTableConfig<F>,
+        region: &mut Region<'_, F>,
+        witness: &Witness<'_, F>,
+        rows: &[(ProcessTableRow<F>, bool)],
+    ) -> Result<Vec<PublicCell<F>>, ErrorFront> {
+        let slots = config.steps.len();
+        let height = rows.len() / slots;
+        // logical rows of the other tables are numbered like ours: memory by
+        // its sorted position, instructions by ip, inputs and outputs in order
+        let mut memory_position = vec![0; rows.len()];
+        for (position, row) in witness.tables.memory_table.iter().enumerate() {
+            if let Some(slot) = memory_position.get_mut(row.clk as usize) {
+                *slot = position;
+            }
+        }
+        let (mut inputs, mut outputs) = (0, 0);
+        let mut public = vec![];
+        for (index, (row, enable)) in rows.iter().enumerate() {
+            let (offset, slot) = (index / slots, index % slots);
+            let step = &config.steps[slot];
+            let clk_cell = region.assign_advice(
+                || "clk",
+                step.clk,
+                offset,
+                || Value::known(F::from(row.clk)),
+            )?;
+            let ip_cell = region.assign_advice(
+                || "ip",
+                step.ip,
+                offset,
+                || Value::known(F::from(row.ip as u64)),
+            )?;
+            region.assign_advice(
+                || "ci",
+                step.ci,
+                offset,
+                || Value::known(F::from(row.ci as u64)),
+            )?;
+            region.assign_advice(
+                || "ni",
+                step.ni,
+                offset,
+                || Value::known(F::from(row.ni as u64)),
+            )?;
+            region.assign_advice(
+                || "mp",
+                step.mp,
+                offset,
+                || Value::known(F::from(row.mp as u64)),
+            )?;
+            region.assign_advice(|| "mv", step.mv, offset, || Value::known(row.mv))?;
+            let mv_is_zero = IsZeroChip::construct(step.mv_is_zero.clone());
+            // the soundness tests forge inverses, see `Forged`
+            #[cfg(test)]
+            let forged = witness.forged.mvi.get(&row.clk);
+            #[cfg(not(test))]
+            let forged: Option<&F> = None;
+            match forged {
+                Some(&mvi) => mv_is_zero.assign_inverse(region, offset, Value::known(mvi))?,
+                None => mv_is_zero.assign(region, offset, Value::known(row.mv))?,
+            }
+            region.assign_advice(
+                || "enable",
+                step.enable,
+                offset,
+                || Value::known(F::from(*enable as u64)),
+            )?;
+
+            let io_index = match (*enable, row.ci) {
+                (true, GETCHAR) => {
+                    inputs += 1;
+                    inputs - 1
+                }
+                (true, PUTCHAR) => {
+                    outputs += 1;
+                    outputs - 1
                 }
+                _ => 0,
+            };
+            let memory_index = if *enable {
+                memory_position[row.clk as usize]
+            } else {
+                0
+            };
+            step.memory_tag
+                .assign(region, offset, memory_index % slots)?;
+            step.ip_tag.assign(region, offset, row.ip % slots)?;
+            step.io_tag.assign(region, offset, io_index % slots)?;
 
-                Ok(())
-            },
-        )
+            if slot == 0 {
+                if offset == 0 {
+                    region.enable_selector(|| "s_b", &config.s_b, offset)?;
+                }
+                region.enable_selector(|| "s_c", &config.s_c, offset)?;
+                if offset != height - 1 {
+                    region.enable_selector(|| "s_p", &config.s_p, offset)?;
+                } else {
+                    region.enable_selector(|| "s_last", &config.s_last, offset)?;
+                }
+            }
+            if index == rows.len() - 1 {
+                public.push(PublicCell {
+                    row: PROGRAM_LEN_ROW,
+                    cell: ip_cell,
+                });
+                if config.expose_final_clk {
+                    public.push(PublicCell {
+                        row: FINAL_CLK_ROW,
+                        cell: clk_cell,
+                    });
+                }
+            }
+        }
+
+        Ok(public)
+    }
+}
+/// The cell value a GETCHAR or PUTCHAR step in `slot` moves: GETCHAR steps
+/// write it into the next step and PUTCHAR steps read it from the current one.
+fn io_value<F: PrimeField>(
+    meta: &mut VirtualCells<F>,
+    steps: &[ProcessorStep<F>],
+    slot: usize,
+    io: u8,
+) -> Expression<F> {
+    if io == GETCHAR {
+        let (next, rotation) = next_slot(slot, steps.len());
+        meta.query_advice(steps[next].mv, rotation)
+    } else {
+        meta.query_advice(steps[slot].mv, Rotation::cur())
     }
 }
-fn create_deselector(instruction: u8, instructions: &[u8]) -> Expression<Fr> {
-    let one = Expression::Constant(Fr::ONE);
+/// Lagrange basis polynomial over `domain`: evaluates to 1 when `ci` equals
+/// `instruction` and to 0 for every other value in `domain`.
+pub(crate) fn create_deselector<F: PrimeField>(
+    ci: Expression<F>,
+    instruction: u8,
+    domain: &[u8],
+) -> Expression<F> {
+    let one = Expression::Constant(F::ONE);
 
-    instructions
-        .into_iter()
+    domain
+        .iter()
         .filter(|&&x| x != instruction)
-        .fold(one, |acc, cur| {
-            acc * (Expression::Constant(Fr::from(*cur as u64))
-                - Expression::Constant(Fr::from(*cur as u64)))
+        .fold(one, |acc, &cur| {
+            let denominator = (F::from(instruction as u64) - F::from(cur as u64))
+                .invert()
+                .unwrap();
+            acc * (ci.clone() - Expression::Constant(F::from(cur as u64)))
+                * Expression::Constant(denominator)
         })
 }
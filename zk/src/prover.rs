@@ -0,0 +1,582 @@
+use std::fmt;
+
+use halo2_proofs::{
+    arithmetic::CurveAffine,
+    halo2curves::{
+        bn256::{Bn256, Fr, G1Affine},
+        ff::{FromUniformBytes, PrimeField},
+        pasta::{EqAffine, Fp},
+        serde::SerdeObject,
+    },
+    plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, Error, ProvingKey, VerifyingKey},
+    poly::{
+        commitment::{Params, ParamsProver},
+        ipa::{
+            commitment::{IPACommitmentScheme, ParamsIPA},
+            multiopen::{ProverIPA, VerifierIPA},
+            strategy::SingleStrategy as IpaStrategy,
+        },
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::{ProverGWC, ProverSHPLONK, VerifierGWC, VerifierSHPLONK},
+            strategy::SingleStrategy as KzgStrategy,
+        },
+    },
+    transcript::{
+        Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
+    },
+};
+use rand_core::{OsRng, RngCore};
+use vm::{error::VmError, interpreter::Interpreter, table::Tables};
+
+use crate::{
+    circuit::BrainfuckCircuit,
+    envelope::{Fingerprint, StoredKey},
+    params::BrainfuckParams,
+    receipt::{check_params, journal, verify_receipt, Journal, Receipt, ReceiptError},
+    setup::{load_kzg, KzgSource, SetupError},
+    sizing::{min_k, params_k, SizeError},
+};
+
+/// A polynomial commitment scheme, with the curve it commits over. The
+/// circuit is defined over the curve's scalar field.
+pub trait Backend {
+    type Scalar: PrimeField + FromUniformBytes<64> + SerdeObject;
+    type Curve: CurveAffine<ScalarExt = Self::Scalar> + SerdeObject;
+    /// Public parameters of the scheme for circuits of `2^k` rows.
+    type Setup: for<'p> Params<'p, Self::Curve>;
+    /// Where the public parameters come from.
+    type Source;
+    /// Names the curve in stored keys.
+    const CURVE: &'static str;
+
+    /// Names the scheme in stored proofs, which only verify under the scheme
+    /// they were made with.
+    fn name(&self) -> &'static str;
+
+    /// Loads or generates the public parameters for circuits of `2^k` rows.
+    fn load_setup(source: &Self::Source, k: u32) -> Result<Self::Setup, SetupError>;
+    fn k(setup: &Self::Setup) -> u32;
+    fn keygen(
+        setup: &Self::Setup,
+        circuit: &BrainfuckCircuit<Self::Scalar>,
+    ) -> Result<ProvingKey<Self::Curve>, Error>;
+    /// Proves `circuit` against `instances`, returning the transcript.
+    fn create(
+        &self,
+        setup: &Self::Setup,
+        pk: &ProvingKey<Self::Curve>,
+        circuit: &BrainfuckCircuit<Self::Scalar>,
+        instances: &[&[Self::Scalar]],
+        rng: impl RngCore,
+    ) -> Result<Vec<u8>, Error>;
+    fn verify(
+        &self,
+        setup: &Self::Setup,
+        vk: &VerifyingKey<Self::Curve>,
+        instances: &[&[Self::Scalar]],
+        proof: &[u8],
+    ) -> Result<(), Error>;
+}
+
+/// Multi-open argument a KZG proof opens its polynomial commitments with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MultiOpen {
+    /// Smaller proofs.
+    #[default]
+    Shplonk,
+    /// Cheaper verification when few polynomials are opened at each point.
+    Gwc,
+}
+
+/// KZG commitments over bn256. Proofs are small and cheap to verify on
+/// Ethereum, but the setup parameters must come from a trusted setup, see
+/// [`crate::setup`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Kzg {
+    pub multiopen: MultiOpen,
+}
+
+impl Backend for Kzg {
+    type Scalar = Fr;
+    type Curve = G1Affine;
+    type Setup = ParamsKZG<Bn256>;
+    type Source = KzgSource;
+    const CURVE: &'static str = "bn256";
+
+    fn name(&self) -> &'static str {
+        match self.multiopen {
+            MultiOpen::Shplonk => "kzg-shplonk",
+            MultiOpen::Gwc => "kzg-gwc",
+        }
+    }
+
+    fn load_setup(source: &KzgSource, k: u32) -> Result<ParamsKZG<Bn256>, SetupError> {
+        load_kzg(source, k)
+    }
+    fn k(setup: &ParamsKZG<Bn256>) -> u32 {
+        setup.k()
+    }
+    fn keygen(
+        setup: &ParamsKZG<Bn256>,
+        circuit: &BrainfuckCircuit<Fr>,
+    ) -> Result<ProvingKey<G1Affine>, Error> {
+        let vk = keygen_vk(setup, circuit)?;
+        keygen_pk(setup, vk, circuit)
+    }
+    fn create(
+        &self,
+        setup: &ParamsKZG<Bn256>,
+        pk: &ProvingKey<G1Affine>,
+        circuit: &BrainfuckCircuit<Fr>,
+        instances: &[&[Fr]],
+        rng: impl RngCore,
+    ) -> Result<Vec<u8>, Error> {
+        let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+        match self.multiopen {
+            MultiOpen::Shplonk => {
+                create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+                    setup,
+                    pk,
+                    std::slice::from_ref(circuit),
+                    &[instances],
+                    rng,
+                    &mut transcript,
+                )?
+            }
+            MultiOpen::Gwc => {
+                create_proof::<KZGCommitmentScheme<Bn256>, ProverGWC<'_, Bn256>, _, _, _, _>(
+                    setup,
+                    pk,
+                    std::slice::from_ref(circuit),
+                    &[instances],
+                    rng,
+                    &mut transcript,
+                )?
+            }
+        }
+        Ok(transcript.finalize())
+    }
+    fn verify(
+        &self,
+        setup: &ParamsKZG<Bn256>,
+        vk: &VerifyingKey<G1Affine>,
+        instances: &[&[Fr]],
+        proof: &[u8],
+    ) -> Result<(), Error> {
+        let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(proof);
+        let verifier_params = setup.verifier_params();
+        match self.multiopen {
+            MultiOpen::Shplonk => verify_proof::<
+                KZGCommitmentScheme<Bn256>,
+                VerifierSHPLONK<'_, Bn256>,
+                _,
+                _,
+                KzgStrategy<'_, Bn256>,
+            >(
+                verifier_params,
+                vk,
+                KzgStrategy::new(verifier_params),
+                &[instances],
+                &mut transcript,
+            )?,
+            MultiOpen::Gwc => verify_proof::<
+                KZGCommitmentScheme<Bn256>,
+                VerifierGWC<'_, Bn256>,
+                _,
+                _,
+                KzgStrategy<'_, Bn256>,
+            >(
+                verifier_params,
+                vk,
+                KzgStrategy::new(verifier_params),
+                &[instances],
+                &mut transcript,
+            )?,
+        };
+        Ok(())
+    }
+}
+
+/// Inner product argument commitments over the Pasta curves. Needs no
+/// trusted setup, and the Pallas/Vesta cycle allows recursion, at the cost
+/// of larger proofs and verification linear in the circuit size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Ipa;
+
+impl Backend for Ipa {
+    type Scalar = Fp;
+    type Curve = EqAffine;
+    type Setup = ParamsIPA<EqAffine>;
+    /// The parameters are derived from `k` alone.
+    type Source = ();
+    const CURVE: &'static str = "vesta";
+
+    fn name(&self) -> &'static str {
+        "ipa"
+    }
+
+    fn load_setup(_source: &(), k: u32) -> Result<ParamsIPA<EqAffine>, SetupError> {
+        Ok(ParamsIPA::new(k))
+    }
+    fn k(setup: &ParamsIPA<EqAffine>) -> u32 {
+        setup.k()
+    }
+    fn keygen(
+        setup: &ParamsIPA<EqAffine>,
+        circuit: &BrainfuckCircuit<Fp>,
+    ) -> Result<ProvingKey<EqAffine>, Error> {
+        let vk = keygen_vk(setup, circuit)?;
+        keygen_pk(setup, vk, circuit)
+    }
+    fn create(
+        &self,
+        setup: &ParamsIPA<EqAffine>,
+        pk: &ProvingKey<EqAffine>,
+        circuit: &BrainfuckCircuit<Fp>,
+        instances: &[&[Fp]],
+        rng: impl RngCore,
+    ) -> Result<Vec<u8>, Error> {
+        let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+        create_proof::<IPACommitmentScheme<EqAffine>, ProverIPA<'_, EqAffine>, _, _, _, _>(
+            setup,
+            pk,
+            std::slice::from_ref(circuit),
+            &[instances],
+            rng,
+            &mut transcript,
+        )?;
+        Ok(transcript.finalize())
+    }
+    fn verify(
+        &self,
+        setup: &ParamsIPA<EqAffine>,
+        vk: &VerifyingKey<EqAffine>,
+        instances: &[&[Fp]],
+        proof: &[u8],
+    ) -> Result<(), Error> {
+        let mut transcript = Blake2bRead::<_, EqAffine, Challenge255<_>>::init(proof);
+        let verifier_params = setup.verifier_params();
+        verify_proof::<
+            IPACommitmentScheme<EqAffine>,
+            VerifierIPA<'_, EqAffine>,
+            _,
+            _,
+            IpaStrategy<'_, EqAffine>,
+        >(
+            verifier_params,
+            vk,
+            IpaStrategy::new(verifier_params),
+            &[instances],
+            &mut transcript,
+        )?;
+        Ok(())
+    }
+}
+
+/// A proof of a run, with the instance columns it was made for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof<F = Fr> {
+    pub instances: Vec<Vec<F>>,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum ProverError {
+    /// The program failed to compile or run.
+    Vm(VmError),
+    /// The circuit or the trace does not fit.
+    Size(SizeError),
+    /// The setup parameters are for another circuit size.
+    SetupSize { k: u32, expected: u32 },
+    /// Key generation or proving failed, or the proof does not verify.
+    Halo2(Error),
+}
+
+impl fmt::Display for ProverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProverError::Vm(error) => write!(f, "program failed: {error}"),
+            ProverError::Size(error) => write!(f, "{error}"),
+            ProverError::SetupSize { k, expected } => {
+                write!(
+                    f,
+                    "setup parameters have k = {k}, the circuit needs {expected}"
+                )
+            }
+            ProverError::Halo2(error) => write!(f, "halo2: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ProverError {}
+
+impl From<VmError> for ProverError {
+    fn from(error: VmError) -> Self {
+        ProverError::Vm(error)
+    }
+}
+impl From<SizeError> for ProverError {
+    fn from(error: SizeError) -> Self {
+        ProverError::Size(error)
+    }
+}
+impl From<Error> for ProverError {
+    fn from(error: Error) -> Self {
+        ProverError::Halo2(error)
+    }
+}
+
+/// Generates the proving key of circuits with `params`. Keys only depend on
+/// the parameters and the setup, not on the program proven.
+pub fn keygen<B: Backend>(
+    setup: &B::Setup,
+    params: BrainfuckParams,
+) -> Result<ProvingKey<B::Curve>, ProverError> {
+    check_setup::<B>(setup, &params)?;
+    let circuit = BrainfuckCircuit::new(Tables::default(), params);
+    Ok(B::keygen(setup, &circuit)?)
+}
+
+/// Checks `setup` has the size circuits with `params` need, returning it.
+pub(crate) fn check_setup<B: Backend>(
+    setup: &B::Setup,
+    params: &BrainfuckParams,
+) -> Result<u32, ProverError> {
+    let expected = params_k(params, B::Scalar::S)?;
+    let k = B::k(setup);
+    if k != expected {
+        return Err(ProverError::SetupSize { k, expected });
+    }
+    Ok(k)
+}
+
+/// Proves `circuit`, whose instance columns are `instances`.
+pub fn create<B: Backend>(
+    backend: &B,
+    setup: &B::Setup,
+    pk: &ProvingKey<B::Curve>,
+    circuit: &BrainfuckCircuit<B::Scalar>,
+    instances: Vec<Vec<B::Scalar>>,
+    rng: impl RngCore,
+) -> Result<Proof<B::Scalar>, ProverError> {
+    let columns = instances.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let bytes = backend.create(setup, pk, circuit, &columns, rng)?;
+    Ok(Proof { instances, bytes })
+}
+
+/// Checks `proof` against its instance columns.
+pub fn verify<B: Backend>(
+    backend: &B,
+    setup: &B::Setup,
+    vk: &VerifyingKey<B::Curve>,
+    proof: &Proof<B::Scalar>,
+) -> Result<(), ProverError> {
+    let columns = proof
+        .instances
+        .iter()
+        .map(Vec::as_slice)
+        .collect::<Vec<_>>();
+    Ok(backend.verify(setup, vk, &columns, &proof.bytes)?)
+}
+
+/// Proves runs of Brainfuck programs under fixed circuit parameters,
+/// generating the keys once.
+pub struct Prover<B: Backend = Kzg> {
+    backend: B,
+    setup: B::Setup,
+    pk: ProvingKey<B::Curve>,
+    params: BrainfuckParams,
+}
+impl<B: Backend> Prover<B> {
+    /// `setup` must have the size `sizing::params_k` gives for `params`.
+    pub fn new(backend: B, setup: B::Setup, params: BrainfuckParams) -> Result<Self, ProverError> {
+        let pk = keygen::<B>(&setup, params)?;
+        Ok(Self {
+            backend,
+            setup,
+            pk,
+            params,
+        })
+    }
+
+    /// A prover with a proving key loaded from an envelope. `setup` must have
+    /// the size the key was made for.
+    pub fn from_key(
+        backend: B,
+        setup: B::Setup,
+        key: StoredKey<ProvingKey<B::Curve>>,
+    ) -> Result<Self, ProverError> {
+        if B::k(&setup) != key.k {
+            return Err(ProverError::SetupSize {
+                k: B::k(&setup),
+                expected: key.k,
+            });
+        }
+        Ok(Self {
+            backend,
+            setup,
+            pk: key.key,
+            params: key.params,
+        })
+    }
+
+    pub fn params(&self) -> &BrainfuckParams {
+        &self.params
+    }
+
+    pub fn setup(&self) -> &B::Setup {
+        &self.setup
+    }
+
+    pub fn pk(&self) -> &ProvingKey<B::Curve> {
+        &self.pk
+    }
+
+    pub fn vk(&self) -> &VerifyingKey<B::Curve> {
+        self.pk.get_vk()
+    }
+
+    /// Fingerprint of the circuit configuration, recorded in stored keys and
+    /// proofs.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::new::<B>(&self.params, B::k(&self.setup))
+    }
+
+    /// Runs `code` on `input` and proves the run.
+    pub fn prove(&self, code: &[u8], input: &[B::Scalar]) -> Result<Proof<B::Scalar>, ProverError> {
+        let mut interpreter =
+            Interpreter::try_with_config(code.to_vec(), input.to_vec(), self.params.vm_config())?;
+        interpreter.try_run(self.params.max_steps)?;
+        // fails if the trace does not fit the parameters
+        min_k(&interpreter.tables, &self.params, B::k(&self.setup))?;
+        let circuit = BrainfuckCircuit::new(interpreter.tables, self.params);
+        let instances = circuit.instances();
+        create(
+            &self.backend,
+            &self.setup,
+            &self.pk,
+            &circuit,
+            instances,
+            OsRng,
+        )
+    }
+
+    pub fn verify(&self, proof: &Proof<B::Scalar>) -> Result<(), ProverError> {
+        verify(&self.backend, &self.setup, self.vk(), proof)
+    }
+
+    /// Runs `code` on the bytes `input` and proves the run, returning the
+    /// proof with its journal. The parameters must expose what the journal
+    /// holds, see `receipt::check_params`.
+    pub fn prove_receipt(
+        &self,
+        code: &[u8],
+        input: &[u8],
+    ) -> Result<Receipt<B::Scalar>, ReceiptError> {
+        check_params(&self.params)?;
+        let input = input
+            .iter()
+            .map(|&byte| B::Scalar::from(byte as u64))
+            .collect::<Vec<_>>();
+        let proof = self.prove(code, &input)?;
+        let journal = journal(&self.params, &proof)?;
+        Ok(Receipt { proof, journal })
+    }
+
+    /// Checks `receipt`, returning its journal only if the proof verifies.
+    pub fn verify_receipt(&self, receipt: &Receipt<B::Scalar>) -> Result<Journal, ReceiptError> {
+        verify_receipt(&self.backend, &self.setup, &self.params, self.vk(), receipt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vm::interpreter::{ADD, PUTCHAR, SUB};
+
+    use super::*;
+    use crate::{
+        fixtures::{self, params, CODE},
+        public_inputs::PublicInputs,
+    };
+
+    /// Proves and verifies a run, then checks claims of another output or
+    /// another program are rejected.
+    fn prove_and_verify<B: Backend>(backend: B, source: B::Source) {
+        let params = BrainfuckParams {
+            expose_program: true,
+            ..params()
+        };
+        let prover = fixtures::prover(backend, &source, params);
+        let proof = prover.prove(&CODE, &[B::Scalar::from(97)]).unwrap();
+        prover.verify(&proof).unwrap();
+
+        let params = prover.params();
+        let public = PublicInputs::from_instances(params, &proof.instances).unwrap();
+        let mut output = public.clone();
+        output.outputs[0] = B::Scalar::ZERO;
+        let mut program = public;
+        program.program.as_mut().unwrap()[0] = SUB;
+        for forged in [output, program] {
+            let forged = Proof {
+                instances: forged.to_instances(params),
+                bytes: proof.bytes.clone(),
+            };
+            assert!(matches!(prover.verify(&forged), Err(ProverError::Halo2(_))));
+        }
+    }
+
+    #[test]
+    fn test_prove_and_verify_kzg() {
+        for multiopen in [MultiOpen::Shplonk, MultiOpen::Gwc] {
+            prove_and_verify(Kzg { multiopen }, KzgSource::InsecureLocal);
+        }
+    }
+
+    /// Copy constraints are fixed at keygen, so they must bind the outputs
+    /// of every run, in every slot of a packed table and on padding rows.
+    #[test]
+    fn test_outputs_bound() {
+        let params = BrainfuckParams {
+            steps_per_row: 2,
+            ..params()
+        };
+        let prover = fixtures::prover(Kzg::default(), &KzgSource::InsecureLocal, params);
+        let code = [ADD, PUTCHAR, ADD, PUTCHAR, ADD, PUTCHAR];
+        let proof = prover.prove(&code, &[]).unwrap();
+        prover.verify(&proof).unwrap();
+
+        // the output columns, one per slot, come right before the summary
+        let outputs = proof.instances.len() - 3;
+        assert_eq!(proof.instances[outputs], [Fr::from(1), Fr::from(3)]);
+        assert_eq!(proof.instances[outputs + 1], [Fr::from(2)]);
+        // claim another output in the second slot
+        let mut forged = proof.clone();
+        forged.instances[outputs + 1][0] = Fr::from(5);
+        assert!(matches!(prover.verify(&forged), Err(ProverError::Halo2(_))));
+        // and an output on a padding row
+        let mut forged = proof;
+        forged.instances[outputs].push(Fr::from(4));
+        assert!(matches!(prover.verify(&forged), Err(ProverError::Halo2(_))));
+    }
+
+    #[test]
+    fn test_prove_and_verify_ipa() {
+        prove_and_verify(Ipa, ());
+    }
+
+    #[test]
+    fn test_setup_size_checked() {
+        let params = BrainfuckParams::default();
+        let k = params_k(&params, Fr::S).unwrap();
+        let setup = Kzg::load_setup(&KzgSource::InsecureLocal, k + 1).unwrap();
+        assert!(matches!(
+            Prover::new(Kzg::default(), setup, params),
+            Err(ProverError::SetupSize { .. })
+        ));
+        assert!(matches!(
+            Prover::new(Ipa, Ipa::load_setup(&(), k - 1).unwrap(), params),
+            Err(ProverError::SetupSize { .. })
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+//! Public inputs of a run and their layout in the instance columns.
+//!
+//! The circuit creates its instance columns in a fixed order: the program
+//! if exposed, the inputs if public, the outputs if the circuit has I/O,
+//! then the summary column. Tables packing several logical rows into each
+//! row spread their values over one column per slot, logical row `i` going
+//! to row `i / steps_per_row` of column `i % steps_per_row`. Provers lay
+//! public inputs out with [`PublicInputs::to_instances`], and verifiers
+//! decode the instance columns of a proof with
+//! [`PublicInputs::from_instances`].
+
+use std::fmt;
+
+use halo2_proofs::halo2curves::{bn256::Fr, ff::PrimeField};
+use vm::table::Tables;
+
+use crate::{
+    circuit::{FINAL_CLK_ROW, INPUT_LEN_ROW, OUTPUT_LEN_ROW, PROGRAM_LEN_ROW},
+    params::{BrainfuckParams, IoMode},
+};
+
+/// Hash of a compiled program, identifying the program a proof ran.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProgramId(pub [u8; 32]);
+
+impl ProgramId {
+    /// The id of `program`, as compiled by `vm::interpreter::compile_code`.
+    pub fn new(program: &[u8]) -> Self {
+        let hash = blake2b_simd::Params::new()
+            .hash_length(32)
+            .personal(b"brainfuck-prog")
+            .hash(program);
+        Self(hash.as_bytes().try_into().unwrap())
+    }
+}
+
+impl fmt::Display for ProgramId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl fmt::Debug for ProgramId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ProgramId({self})")
+    }
+}
+
+/// What a proof of a run claims.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicInputs<F = Fr> {
+    /// The compiled program, when `BrainfuckParams::expose_program` is set.
+    pub program: Option<Vec<u8>>,
+    /// Length of the compiled program, which is the ip the run halts at.
+    pub program_len: u64,
+    /// Input values, when `IoMode::Public`.
+    pub inputs: Option<Vec<F>>,
+    /// Number of inputs read, public in every I/O mode.
+    pub input_len: u64,
+    /// Output values, empty when the circuit has no I/O.
+    pub outputs: Vec<F>,
+    /// Clk of the halt row, which is the number of steps taken, when
+    /// `BrainfuckParams::expose_final_clk` is set.
+    pub final_clk: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PublicInputsError {
+    /// The number of instance columns does not match the parameters.
+    Columns { expected: usize, found: usize },
+    /// A value is out of range, or disagrees with the lengths.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for PublicInputsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublicInputsError::Columns { expected, found } => {
+                write!(f, "expected {expected} instance columns, found {found}")
+            }
+            PublicInputsError::Malformed(what) => write!(f, "malformed public inputs: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for PublicInputsError {}
+
+impl<F: PrimeField> PublicInputs<F> {
+    /// The public inputs of the run that produced `tables`.
+    pub fn from_tables(tables: &Tables<F>, params: &BrainfuckParams) -> Self {
+        // the instruction table ends with the halt position
+        let (halt, program) = tables
+            .instruction_table
+            .split_last()
+            .map_or((0, &[][..]), |(halt, program)| (halt.ip, program));
+        Self {
+            program: params
+                .expose_program
+                .then(|| program.iter().map(|row| row.ci).collect()),
+            program_len: halt as u64,
+            inputs: (params.io_mode == IoMode::Public)
+                .then(|| tables.input_table.iter().map(|row| row.value).collect()),
+            input_len: tables.input_table.len() as u64,
+            outputs: tables.output_table.iter().map(|row| row.value).collect(),
+            final_clk: params
+                .expose_final_clk
+                .then(|| tables.processor_table.last().map_or(0, |row| row.clk)),
+        }
+    }
+
+    /// The id of the program, when it is public.
+    pub fn program_id(&self) -> Option<ProgramId> {
+        self.program.as_deref().map(ProgramId::new)
+    }
+
+    /// Lays the public inputs out in the instance columns of a circuit with
+    /// `params`.
+    ///
+    /// Panics if a value `params` exposes is missing.
+    pub fn to_instances(&self, params: &BrainfuckParams) -> Vec<Vec<F>> {
+        let slots = params.steps_per_row;
+        let mut columns = vec![];
+        if params.expose_program {
+            let program = self.program.as_ref().expect("the program is public");
+            let program = program
+                .iter()
+                .map(|&byte| F::from(byte as u64))
+                .collect::<Vec<_>>();
+            columns.extend(spread(&program, slots));
+        }
+        if params.io_mode == IoMode::Public {
+            let inputs = self.inputs.as_ref().expect("the inputs are public");
+            columns.extend(spread(inputs, slots));
+        }
+        if params.has_io() {
+            columns.extend(spread(&self.outputs, slots));
+        }
+
+        let mut summary = vec![F::ZERO; PROGRAM_LEN_ROW + 1];
+        summary[INPUT_LEN_ROW] = F::from(self.input_len);
+        summary[OUTPUT_LEN_ROW] = F::from(self.outputs.len() as u64);
+        summary[PROGRAM_LEN_ROW] = F::from(self.program_len);
+        if params.expose_final_clk {
+            let final_clk = self.final_clk.expect("the final clk is public");
+            summary.resize(FINAL_CLK_ROW + 1, F::ZERO);
+            summary[FINAL_CLK_ROW] = F::from(final_clk);
+        }
+        columns.push(summary);
+        columns
+    }
+
+    /// Decodes the instance columns of a circuit with `params`.
+    pub fn from_instances(
+        params: &BrainfuckParams,
+        columns: &[Vec<F>],
+    ) -> Result<Self, PublicInputsError> {
+        let slots = params.steps_per_row;
+        let exposed = [
+            params.expose_program,
+            params.io_mode == IoMode::Public,
+            params.has_io(),
+        ];
+        let expected = exposed.iter().filter(|&&exposed| exposed).count() * slots + 1;
+        if columns.len() != expected {
+            return Err(PublicInputsError::Columns {
+                expected,
+                found: columns.len(),
+            });
+        }
+        let (summary, columns) = columns.split_last().unwrap();
+        let mut tables = columns.chunks(slots);
+
+        let summary_len = if params.expose_final_clk {
+            FINAL_CLK_ROW + 1
+        } else {
+            PROGRAM_LEN_ROW + 1
+        };
+        if summary.len() != summary_len {
+            return Err(PublicInputsError::Malformed("wrong summary length"));
+        }
+        let summary = summary
+            .iter()
+            .map(|value| to_u64(value).ok_or(PublicInputsError::Malformed("summary out of range")))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (input_len, output_len) = (summary[INPUT_LEN_ROW], summary[OUTPUT_LEN_ROW]);
+        if !params.has_io() && (input_len, output_len) != (0, 0) {
+            return Err(PublicInputsError::Malformed("I/O without I/O tables"));
+        }
+
+        let program = if params.expose_program {
+            let program = gather(tables.next().unwrap(), summary[PROGRAM_LEN_ROW])?
+                .iter()
+                .map(|value| {
+                    to_u64(value)
+                        .and_then(|byte| u8::try_from(byte).ok())
+                        .ok_or(PublicInputsError::Malformed("program byte out of range"))
+                })
+                .collect::<Result<_, _>>()?;
+            Some(program)
+        } else {
+            None
+        };
+        let inputs = if params.io_mode == IoMode::Public {
+            Some(gather(tables.next().unwrap(), input_len)?)
+        } else {
+            None
+        };
+        let outputs = if params.has_io() {
+            gather(tables.next().unwrap(), output_len)?
+        } else {
+            vec![]
+        };
+        Ok(Self {
+            program,
+            program_len: summary[PROGRAM_LEN_ROW],
+            inputs,
+            input_len,
+            outputs,
+            final_clk: params.expose_final_clk.then(|| summary[FINAL_CLK_ROW]),
+        })
+    }
+}
+
+/// Spreads logical rows over one column per slot.
+fn spread<F: Copy>(values: &[F], slots: usize) -> Vec<Vec<F>> {
+    (0..slots)
+        .map(|slot| values.iter().skip(slot).step_by(slots).copied().collect())
+        .collect()
+}
+
+/// Reads `len` logical rows back from the columns of a table. Rows past
+/// `len` go to disabled rows of the table and must be zero.
+fn gather<F: PrimeField>(columns: &[Vec<F>], len: u64) -> Result<Vec<F>, PublicInputsError> {
+    let slots = columns.len();
+    let height = columns.iter().map(Vec::len).max().unwrap_or(0);
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|&len| len <= height * slots)
+        .ok_or(PublicInputsError::Malformed("length exceeds the values"))?;
+    let values = (0..height * slots)
+        .map(|index| {
+            columns[index % slots]
+                .get(index / slots)
+                .copied()
+                .unwrap_or(F::ZERO)
+        })
+        .collect::<Vec<_>>();
+    if values[len..]
+        .iter()
+        .any(|value| !bool::from(value.is_zero()))
+    {
+        return Err(PublicInputsError::Malformed("values past the length"));
+    }
+    Ok(values[..len].to_vec())
+}
+
+/// The value of a field element below `2^64`. The fields of halo2curves
+/// store their representation little-endian.
+pub(crate) fn to_u64<F: PrimeField>(value: &F) -> Option<u64> {
+    let repr = value.to_repr();
+    let (low, high) = repr.as_ref().split_at(8);
+    high.iter()
+        .all(|&byte| byte == 0)
+        .then(|| u64::from_le_bytes(low.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::{arithmetic::Field, halo2curves::pasta::Fp};
+    use vm::interpreter::Interpreter;
+
+    use super::*;
+    use crate::fixtures::CODE;
+
+    fn run(params: &BrainfuckParams) -> PublicInputs<Fp> {
+        let mut interpreter =
+            Interpreter::with_config(CODE.to_vec(), vec![Fp::from(97)], params.vm_config());
+        interpreter.run();
+        PublicInputs::from_tables(&interpreter.tables, params)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for (io_mode, steps_per_row, exposed) in [
+            (IoMode::Public, 1, false),
+            (IoMode::Public, 3, true),
+            (IoMode::PrivateInput, 2, true),
+        ] {
+            let params = BrainfuckParams {
+                io_mode,
+                steps_per_row,
+                expose_final_clk: exposed,
+                expose_program: exposed,
+                ..BrainfuckParams::default()
+            };
+            let public = run(&params);
+            assert_eq!(public.outputs, vec![Fp::from(98), Fp::from(99)]);
+            assert_eq!(public.input_len, 1);
+            assert_eq!(public.inputs.is_some(), io_mode == IoMode::Public);
+            assert_eq!(public.program_id().is_some(), exposed);
+
+            let columns = public.to_instances(&params);
+            assert_eq!(
+                PublicInputs::from_instances(&params, &columns).unwrap(),
+                public
+            );
+        }
+    }
+
+    #[test]
+    fn test_malformed_rejected() {
+        let params = BrainfuckParams {
+            steps_per_row: 2,
+            expose_program: true,
+            ..BrainfuckParams::default()
+        };
+        let columns = run(&params).to_instances(&params);
+        assert_eq!(columns.len(), 7);
+        let decode = |columns: &[Vec<Fp>]| PublicInputs::from_instances(&params, columns);
+
+        assert!(matches!(
+            decode(&columns[1..]),
+            Err(PublicInputsError::Columns {
+                expected: 7,
+                found: 6
+            })
+        ));
+        // an output past the output length
+        let mut extra = columns.clone();
+        extra[5].push(Fp::from(1));
+        assert!(decode(&extra).is_err());
+        let mut long = columns.clone();
+        long[6][OUTPUT_LEN_ROW] = Fp::from(100);
+        assert!(decode(&long).is_err());
+        let mut byte = columns.clone();
+        byte[0][0] = Fp::from(256);
+        assert!(decode(&byte).is_err());
+        let mut negative = columns;
+        negative[6][PROGRAM_LEN_ROW] = -Fp::ONE;
+        assert!(decode(&negative).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! End-to-end property tests: random terminating programs are run by the
+//! interpreter and their traces must satisfy the circuit.
+//!
+//! Programs are generated as trees and then rendered into code that
+//! terminates by construction. Every loop body returns to the cell the loop
+//! started on, so the pointer is known statically; the loop counts that cell
+//! down, nothing else in the body writes to it, and cells wrap around at 8
+//! bits.
+
+use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
+use proptest::prelude::*;
+use vm::interpreter::{Interpreter, ADD, GETCHAR, LB, PUTCHAR, RB, SHL, SHR, SUB};
+
+use crate::{
+    circuit::BrainfuckCircuit,
+    diagnostics::{Diagnostics, SourceMap},
+    params::BrainfuckParams,
+    sizing::{min_k, MAX_K},
+};
+
+#[derive(Clone, Debug)]
+enum Node {
+    Op(u8),
+    Loop(Vec<Node>),
+}
+
+/// Renders a program tree into code, dropping the instructions that would
+/// leave the tape, write to the counter of an enclosing loop, or read past
+/// the end of the input.
+struct Renderer {
+    tape_size: usize,
+    /// inputs left to read; only read outside loops, so each `,` runs once
+    inputs: usize,
+    ptr: usize,
+    /// cells counted down by the enclosing loops
+    counters: Vec<usize>,
+    code: Vec<u8>,
+}
+impl Renderer {
+    fn render(nodes: &[Node], tape_size: usize, inputs: usize) -> Vec<u8> {
+        let mut renderer = Self {
+            tape_size,
+            inputs,
+            ptr: 0,
+            counters: vec![],
+            code: vec![],
+        };
+        renderer.nodes(nodes);
+        renderer.code
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            let is_counter = self.counters.contains(&self.ptr);
+            match node {
+                Node::Op(SHL) if self.ptr > 0 => {
+                    self.ptr -= 1;
+                    self.code.push(SHL);
+                }
+                Node::Op(SHR) if self.ptr + 1 < self.tape_size => {
+                    self.ptr += 1;
+                    self.code.push(SHR);
+                }
+                Node::Op(op @ (ADD | SUB)) if !is_counter => self.code.push(*op),
+                Node::Op(GETCHAR) if self.counters.is_empty() && self.inputs > 0 => {
+                    self.inputs -= 1;
+                    self.code.push(GETCHAR);
+                }
+                Node::Op(PUTCHAR) => self.code.push(PUTCHAR),
+                Node::Loop(body) if !is_counter => {
+                    let start = self.ptr;
+                    self.code.push(LB);
+                    self.counters.push(start);
+                    self.nodes(body);
+                    while self.ptr > start {
+                        self.ptr -= 1;
+                        self.code.push(SHL);
+                    }
+                    while self.ptr < start {
+                        self.ptr += 1;
+                        self.code.push(SHR);
+                    }
+                    self.counters.pop();
+                    self.code.extend([SUB, RB]);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn node() -> impl Strategy<Value = Node> {
+    let op = prop::sample::select(vec![ADD, SUB, SHL, SHR, GETCHAR, PUTCHAR]).prop_map(Node::Op);
+    op.prop_recursive(2, 24, 4, |inner| {
+        prop::collection::vec(inner, 1..5).prop_map(Node::Loop)
+    })
+}
+
+/// A program tree, its input and the number of steps packed in each row.
+fn case() -> impl Strategy<Value = (Vec<Node>, Vec<u8>, usize)> {
+    (
+        prop::collection::vec(node(), 1..12),
+        prop::collection::vec(0u8..4, 0..6),
+        1usize..=3,
+    )
+}
+
+fn params(steps_per_row: usize) -> BrainfuckParams {
+    BrainfuckParams {
+        cell_bits: Some(8),
+        tape_size: 4,
+        max_program_len: 64,
+        max_steps: 512,
+        steps_per_row,
+        expose_program: true,
+        ..BrainfuckParams::default()
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn test_random_programs((nodes, input, steps_per_row) in case()) {
+        let params = params(steps_per_row);
+        let code = Renderer::render(&nodes, params.tape_size, input.len());
+        let input = input.into_iter().map(|value| Fr::from(value as u64)).collect::<Vec<_>>();
+        let mut interpreter = Interpreter::with_config(code.clone(), input, params.vm_config());
+        interpreter.run();
+        let tables = interpreter.tables;
+
+        // programs or runs longer than the parameters allow
+        let k = min_k(&tables, &params, MAX_K);
+        prop_assume!(k.is_ok());
+        let k = k.unwrap();
+        let circuit = BrainfuckCircuit::new(tables.clone(), params);
+        let prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+        if let Err(failures) = prover.verify() {
+            let source = SourceMap::new(&String::from_utf8(code.clone()).unwrap());
+            let diagnostics = Diagnostics::new(&tables, &params, &source);
+            let messages = failures
+                .iter()
+                .map(|failure| diagnostics.explain(failure))
+                .collect::<Vec<_>>();
+            prop_assert!(
+                false,
+                "{} rejected:\n{}",
+                String::from_utf8(code).unwrap(),
+                messages.join("\n")
+            );
+        }
+    }
+}
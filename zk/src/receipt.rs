@@ -0,0 +1,251 @@
+//! Receipts: a proof of a run with a journal of what it proves, so that
+//! applications read verified output instead of decoding instance columns.
+//!
+//! Receipts need the program, the inputs and the final clk public, and
+//! byte-wide cells, see [`check_params`].
+
+use std::fmt;
+
+use halo2_proofs::{
+    halo2curves::{bn256::Fr, ff::PrimeField},
+    plonk::VerifyingKey,
+};
+
+use crate::{
+    params::{BrainfuckParams, IoMode},
+    prover::{verify, Backend, Proof, ProverError},
+    public_inputs::{to_u64, ProgramId, PublicInputs, PublicInputsError},
+};
+
+/// Hash of the input bytes of a run.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InputHash(pub [u8; 32]);
+
+impl InputHash {
+    pub fn new(input: &[u8]) -> Self {
+        let hash = blake2b_simd::Params::new()
+            .hash_length(32)
+            .personal(b"brainfuck-input")
+            .hash(input);
+        Self(hash.as_bytes().try_into().unwrap())
+    }
+}
+
+impl fmt::Display for InputHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl fmt::Debug for InputHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InputHash({self})")
+    }
+}
+
+/// The public data of a run, decoded from its public inputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Journal {
+    pub program_id: ProgramId,
+    /// Bytes the program printed.
+    pub output: Vec<u8>,
+    pub input_hash: InputHash,
+    /// Steps the run took before halting.
+    pub steps: u64,
+}
+
+impl Journal {
+    /// Decodes the journal from the public inputs of a circuit whose
+    /// parameters pass [`check_params`].
+    pub fn new<F: PrimeField>(public: &PublicInputs<F>) -> Result<Self, ReceiptError> {
+        let (Some(program), Some(inputs), Some(final_clk)) =
+            (&public.program, &public.inputs, public.final_clk)
+        else {
+            return Err(ReceiptError::Params);
+        };
+        Ok(Self {
+            program_id: ProgramId::new(program),
+            output: to_bytes(&public.outputs)?,
+            input_hash: InputHash::new(&to_bytes(inputs)?),
+            steps: final_clk,
+        })
+    }
+}
+
+/// A proof of a run and the journal it proves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Receipt<F = Fr> {
+    pub proof: Proof<F>,
+    pub journal: Journal,
+}
+
+#[derive(Debug)]
+pub enum ReceiptError {
+    /// The circuit does not expose the program, the inputs and the final
+    /// clk, or its cells are wider than a byte.
+    Params,
+    /// An input or output value is not a byte.
+    NotBytes,
+    PublicInputs(PublicInputsError),
+    /// The journal is not the one the proof's public inputs give.
+    JournalMismatch,
+    Prover(ProverError),
+}
+
+impl fmt::Display for ReceiptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiptError::Params => write!(
+                f,
+                "receipts need the program, the inputs and the final clk public, and cells \
+                 of at most 8 bits"
+            ),
+            ReceiptError::NotBytes => write!(f, "input or output value is not a byte"),
+            ReceiptError::PublicInputs(error) => write!(f, "{error}"),
+            ReceiptError::JournalMismatch => {
+                write!(f, "journal does not match the proof's public inputs")
+            }
+            ReceiptError::Prover(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for ReceiptError {}
+
+impl From<PublicInputsError> for ReceiptError {
+    fn from(error: PublicInputsError) -> Self {
+        ReceiptError::PublicInputs(error)
+    }
+}
+impl From<ProverError> for ReceiptError {
+    fn from(error: ProverError) -> Self {
+        ReceiptError::Prover(error)
+    }
+}
+
+/// Checks circuits with `params` make proofs a journal can be read from.
+/// Cells must fit a byte so that every output does.
+pub fn check_params(params: &BrainfuckParams) -> Result<(), ReceiptError> {
+    if params.expose_program
+        && params.expose_final_clk
+        && params.io_mode == IoMode::Public
+        && params.cell_bits.is_some_and(|bits| bits <= 8)
+    {
+        Ok(())
+    } else {
+        Err(ReceiptError::Params)
+    }
+}
+
+/// Decodes the journal of `proof`, made by a circuit with `params`.
+pub fn journal<F: PrimeField>(
+    params: &BrainfuckParams,
+    proof: &Proof<F>,
+) -> Result<Journal, ReceiptError> {
+    check_params(params)?;
+    Journal::new(&PublicInputs::from_instances(params, &proof.instances)?)
+}
+
+/// Checks `receipt` against the verifying key of circuits with `params`,
+/// returning its journal only if the proof verifies.
+pub fn verify_receipt<B: Backend>(
+    backend: &B,
+    setup: &B::Setup,
+    params: &BrainfuckParams,
+    vk: &VerifyingKey<B::Curve>,
+    receipt: &Receipt<B::Scalar>,
+) -> Result<Journal, ReceiptError> {
+    let journal = journal(params, &receipt.proof)?;
+    if journal != receipt.journal {
+        return Err(ReceiptError::JournalMismatch);
+    }
+    verify(backend, setup, vk, &receipt.proof)?;
+    Ok(journal)
+}
+
+fn to_bytes<F: PrimeField>(values: &[F]) -> Result<Vec<u8>, ReceiptError> {
+    values
+        .iter()
+        .map(|value| {
+            to_u64(value)
+                .and_then(|byte| u8::try_from(byte).ok())
+                .ok_or(ReceiptError::NotBytes)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::halo2curves::pasta::Fp;
+    use vm::interpreter::compile_code;
+
+    use super::*;
+    use crate::{
+        fixtures::{self, CODE},
+        prover::{Ipa, Prover},
+    };
+
+    fn params() -> BrainfuckParams {
+        BrainfuckParams {
+            cell_bits: Some(8),
+            expose_final_clk: true,
+            expose_program: true,
+            ..fixtures::params()
+        }
+    }
+
+    fn prover(params: BrainfuckParams) -> Prover<Ipa> {
+        fixtures::prover(Ipa, &(), params)
+    }
+
+    #[test]
+    fn test_receipt_verified() {
+        let prover = prover(params());
+        let receipt = prover.prove_receipt(&CODE, b"a").unwrap();
+        let expected = Journal {
+            program_id: ProgramId::new(&compile_code(&CODE).unwrap()),
+            output: b"bc".to_vec(),
+            input_hash: InputHash::new(b"a"),
+            steps: 18,
+        };
+        assert_eq!(receipt.journal, expected);
+        assert_eq!(prover.verify_receipt(&receipt).unwrap(), expected);
+
+        // a journal the proof does not back
+        let mut forged = receipt.clone();
+        forged.journal.output = b"bd".to_vec();
+        assert!(matches!(
+            prover.verify_receipt(&forged),
+            Err(ReceiptError::JournalMismatch)
+        ));
+        // and one whose public inputs were forged along with it
+        let mut public = PublicInputs::from_instances(&params(), &forged.proof.instances).unwrap();
+        public.outputs[1] = Fp::from(b'd' as u64);
+        forged.proof.instances = public.to_instances(&params());
+        assert!(matches!(
+            prover.verify_receipt(&forged),
+            Err(ReceiptError::Prover(ProverError::Halo2(_)))
+        ));
+    }
+
+    #[test]
+    fn test_params_checked() {
+        let params = BrainfuckParams {
+            expose_program: false,
+            ..params()
+        };
+        assert!(matches!(check_params(&params), Err(ReceiptError::Params)));
+        for cell_bits in [None, Some(9)] {
+            let wide = BrainfuckParams {
+                cell_bits,
+                expose_program: true,
+                ..params
+            };
+            assert!(matches!(check_params(&wide), Err(ReceiptError::Params)));
+        }
+        assert!(matches!(
+            prover(params).prove_receipt(&CODE, b"a"),
+            Err(ReceiptError::Params)
+        ));
+    }
+}
@@ -0,0 +1,170 @@
+//! Public parameters of the commitment schemes.
+//!
+//! KZG parameters come out of a trusted setup: proofs are only sound if no
+//! one knows the secret the parameters were made from. Production
+//! parameters are therefore loaded from a file of the perpetual powers of
+//! tau ceremony, converted to the halo2 format, and downsized to the
+//! circuit. Parameters generated locally are only available with the
+//! `insecure-setup` feature, and in this crate's tests.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use halo2_proofs::{
+    halo2curves::bn256::Bn256,
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+};
+
+use crate::prover::Backend;
+
+/// Where KZG parameters come from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KzgSource {
+    /// A file of `ParamsKZG<Bn256>` in the halo2 format, with `k` at least
+    /// the circuit's. Larger parameters are downsized.
+    File(PathBuf),
+    /// Parameters generated from local randomness. Whoever ran the
+    /// generation could forge proofs.
+    #[cfg(any(test, feature = "insecure-setup"))]
+    InsecureLocal,
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    /// The parameters file could not be read.
+    Io(io::Error),
+    /// The parameters file is for circuits smaller than needed.
+    TooSmall { k: u32, available: u32 },
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::Io(error) => write!(f, "cannot read setup parameters: {error}"),
+            SetupError::TooSmall { k, available } => write!(
+                f,
+                "setup parameters have k = {available}, the circuit needs {k}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+impl From<io::Error> for SetupError {
+    fn from(error: io::Error) -> Self {
+        SetupError::Io(error)
+    }
+}
+
+/// Loads KZG parameters for circuits of `2^k` rows from `source`.
+pub fn load_kzg(source: &KzgSource, k: u32) -> Result<ParamsKZG<Bn256>, SetupError> {
+    match source {
+        KzgSource::File(path) => {
+            let mut reader = BufReader::new(File::open(path)?);
+            let mut params = ParamsKZG::<Bn256>::read(&mut reader)?;
+            let available = params.k();
+            if available < k {
+                return Err(SetupError::TooSmall { k, available });
+            }
+            if available > k {
+                params.downsize(k);
+            }
+            Ok(params)
+        }
+        #[cfg(any(test, feature = "insecure-setup"))]
+        KzgSource::InsecureLocal => Ok(ParamsKZG::setup(k, rand_core::OsRng)),
+    }
+}
+
+/// Public parameters of a backend, loaded or generated once per `k` and
+/// shared by every caller.
+pub struct ParamsCache<B: Backend> {
+    source: B::Source,
+    params: Mutex<HashMap<u32, Arc<B::Setup>>>,
+}
+
+impl<B: Backend> ParamsCache<B> {
+    pub fn new(source: B::Source) -> Self {
+        Self {
+            source,
+            params: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn source(&self) -> &B::Source {
+        &self.source
+    }
+
+    /// Parameters for circuits of `2^k` rows.
+    pub fn get(&self, k: u32) -> Result<Arc<B::Setup>, SetupError> {
+        if let Some(setup) = self.params.lock().unwrap().get(&k) {
+            return Ok(setup.clone());
+        }
+        // loading may take long, and other sizes need not wait for it. Of
+        // two concurrent loads of one size the first cached wins, so that
+        // every caller gets the same parameters.
+        let setup = Arc::new(B::load_setup(&self.source, k)?);
+        let mut params = self.params.lock().unwrap();
+        Ok(params.entry(k).or_insert(setup).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::prover::{Ipa, Kzg};
+
+    fn to_bytes(params: &ParamsKZG<Bn256>) -> Vec<u8> {
+        let mut bytes = vec![];
+        params.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn write_params(path: &Path, params: &ParamsKZG<Bn256>) {
+        std::fs::write(path, to_bytes(params)).unwrap();
+    }
+
+    #[test]
+    fn test_file_downsized() {
+        let path = std::env::temp_dir().join(format!("bf-setup-{}.srs", std::process::id()));
+        let params = load_kzg(&KzgSource::InsecureLocal, 5).unwrap();
+        write_params(&path, &params);
+        let source = KzgSource::File(path.clone());
+
+        assert_eq!(to_bytes(&load_kzg(&source, 5).unwrap()), to_bytes(&params));
+        let mut downsized = params.clone();
+        downsized.downsize(3);
+        let loaded = load_kzg(&source, 3).unwrap();
+        assert_eq!(loaded.k(), 3);
+        assert_eq!(to_bytes(&loaded), to_bytes(&downsized));
+        assert!(matches!(
+            load_kzg(&source, 6),
+            Err(SetupError::TooSmall { k: 6, available: 5 })
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(load_kzg(&source, 3), Err(SetupError::Io(_))));
+    }
+
+    #[test]
+    fn test_cached_by_k() {
+        let cache = ParamsCache::<Kzg>::new(KzgSource::InsecureLocal);
+        let params = cache.get(3).unwrap();
+        assert_eq!(params.k(), 3);
+        // a second generation would use other randomness
+        assert!(Arc::ptr_eq(&cache.get(3).unwrap(), &params));
+        assert_eq!(cache.get(4).unwrap().k(), 4);
+
+        let cache = ParamsCache::<Ipa>::new(());
+        assert_eq!(cache.get(3).unwrap().k(), 3);
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+
+use halo2_proofs::{
+    halo2curves::{bn256::Fr, ff::PrimeField},
+    plonk::{Circuit, ConstraintSystem},
+};
+use vm::table::Tables;
+
+use crate::{
+    circuit::{BrainfuckCircuit, FINAL_CLK_ROW},
+    params::BrainfuckParams,
+};
+
+/// Largest `k` over bn256, whose scalar field has `2^28`-th roots of unity
+/// and no higher powers of two.
+pub const MAX_K: u32 = Fr::S;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SizeError {
+    /// A table of the trace has more rows than the parameters make room for.
+    TraceTooLong {
+        table: &'static str,
+        rows: usize,
+        max_rows: usize,
+    },
+    /// The compiled program is longer than the parameters allow.
+    ProgramTooLong { len: usize, max_len: usize },
+    /// The trace reads or prints under `IoMode::None`.
+    UnexpectedIo,
+    /// The circuit needs `2^k` rows, above the configured maximum.
+    CircuitTooLarge { k: u32, max_k: u32 },
+}
+
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SizeError::TraceTooLong {
+                table,
+                rows,
+                max_rows,
+            } => write!(
+                f,
+                "{table} table has {rows} rows but the circuit parameters allow at most {max_rows}"
+            ),
+            SizeError::ProgramTooLong { len, max_len } => write!(
+                f,
+                "program of {len} bytes is longer than the circuit parameters allow ({max_len})"
+            ),
+            SizeError::UnexpectedIo => {
+                write!(
+                    f,
+                    "trace performs I/O but the circuit parameters allow none"
+                )
+            }
+            SizeError::CircuitTooLarge { k, max_k } => {
+                write!(f, "circuit needs k = {k}, above the maximum of {max_k}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SizeError {}
+
+/// Smallest `k` such that a `BrainfuckCircuit` with `params` can prove
+/// `tables`, or an error if the trace does not fit `params` or needs more
+/// than `2^max_k` rows. A trace fits if every table does, its program is at
+/// most `max_program_len` long and it performs I/O only if `params` allow
+/// it.
+///
+/// The circuit's tallest column is one of the padded tables, the lookup
+/// tables or the summary instance column, the other instance columns being
+/// as tall as their tables; halo2 then reserves rows below it for blinding.
+pub fn min_k<F: PrimeField>(
+    tables: &Tables<F>,
+    params: &BrainfuckParams,
+    max_k: u32,
+) -> Result<u32, SizeError> {
+    // the tables are padded to the longer of the run and the program, so
+    // only the bound on ip catches a program that is too long
+    let len = tables.instruction_table.len().saturating_sub(1);
+    if len > params.max_program_len {
+        return Err(SizeError::ProgramTooLong {
+            len,
+            max_len: params.max_program_len,
+        });
+    }
+    // the I/O tables are not built, so only the decode table catches I/O
+    if !params.has_io() && !(tables.input_table.is_empty() && tables.output_table.is_empty()) {
+        return Err(SizeError::UnexpectedIo);
+    }
+    let mut meta = ConstraintSystem::default();
+    let config = BrainfuckCircuit::<F>::configure_with_params(&mut meta, *params);
+    let height = params.height();
+    for table in &config.tables {
+        let (rows, max_rows) = (table.trace_len(tables), table.capacity(height));
+        if rows > max_rows {
+            return Err(SizeError::TraceTooLong {
+                table: table.name(),
+                rows,
+                max_rows,
+            });
+        }
+    }
+
+    let used_rows = [height, config.lookup_rows(), FINAL_CLK_ROW + 1]
+        .into_iter()
+        .max()
+        .unwrap();
+    let rows = (used_rows + meta.blinding_factors() + 1).max(meta.minimum_rows());
+    let k = rows.next_power_of_two().trailing_zeros();
+    if k > max_k {
+        return Err(SizeError::CircuitTooLarge { k, max_k });
+    }
+    Ok(k)
+}
+
+/// Smallest `k` such that a `BrainfuckCircuit` with `params` can prove every
+/// trace within them, which is the `k` its keys are generated for.
+pub fn params_k(params: &BrainfuckParams, max_k: u32) -> Result<u32, SizeError> {
+    // an empty trace fits anything a longer trace does: its instance columns
+    // are never taller than the tables. The circuit has the same shape over
+    // every field.
+    min_k(&Tables::<Fr>::default(), params, max_k)
+}
+
+#[cfg(test)]
+mod tests {
+    use vm::interpreter::{Interpreter, ADD, LB, PUTCHAR, RB, SHR, SUB};
+
+    use super::*;
+    use crate::params::IoMode;
+
+    fn run(code: Vec<u8>, params: &BrainfuckParams) -> Tables {
+        let mut interpreter = Interpreter::with_config(code, vec![], params.vm_config());
+        interpreter.run();
+        interpreter.tables
+    }
+
+    #[test]
+    fn test_range_table_sets_k_of_short_traces() {
+        let params = BrainfuckParams {
+            max_program_len: 8,
+            max_steps: 8,
+            io_mode: IoMode::None,
+            ..BrainfuckParams::default()
+        };
+        let tables = run(vec![ADD, SHR, ADD], &params);
+        // 256 range table rows and the blinding rows need 2^9
+        assert_eq!(min_k(&tables, &params, MAX_K), Ok(9));
+    }
+
+    #[test]
+    fn test_packing_lowers_k() {
+        let code = [vec![ADD; 100], vec![LB, SUB, RB]].concat();
+        let params = BrainfuckParams {
+            max_steps: 1024,
+            io_mode: IoMode::None,
+            ..BrainfuckParams::default()
+        };
+        let tables = run(code.clone(), &params);
+        assert_eq!(min_k(&tables, &params, MAX_K), Ok(11));
+        let packed = BrainfuckParams {
+            steps_per_row: 4,
+            ..params
+        };
+        assert_eq!(min_k(&tables, &packed, MAX_K), Ok(9));
+    }
+
+    #[test]
+    fn test_oversized_traces_rejected() {
+        let params = BrainfuckParams {
+            max_program_len: 8,
+            max_steps: 4,
+            io_mode: IoMode::None,
+            ..BrainfuckParams::default()
+        };
+        // 10 steps and the halt row
+        let tables = run(vec![ADD, ADD, ADD, LB, SUB, RB], &params);
+        assert_eq!(
+            min_k(&tables, &params, MAX_K),
+            Err(SizeError::TraceTooLong {
+                table: "processor",
+                rows: 11,
+                max_rows: 9,
+            })
+        );
+
+        let tables = run(vec![ADD; 4], &params);
+        assert_eq!(
+            min_k(&tables, &params, 8),
+            Err(SizeError::CircuitTooLarge { k: 9, max_k: 8 })
+        );
+
+        // a loop skipped at once, 9 bytes compiled but a single step
+        let tables = run(vec![LB, ADD, ADD, ADD, ADD, ADD, RB], &params);
+        assert_eq!(
+            min_k(&tables, &params, MAX_K),
+            Err(SizeError::ProgramTooLong { len: 9, max_len: 8 })
+        );
+        let tables = run(vec![ADD, PUTCHAR], &params);
+        assert_eq!(min_k(&tables, &params, MAX_K), Err(SizeError::UnexpectedIo));
+    }
+}
@@ -0,0 +1,535 @@
+use std::{collections::HashMap, sync::Arc};
+
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{AssignedCell, Layouter, Region, Value},
+    halo2curves::ff::PrimeField,
+    plonk::{
+        Advice, Any, Column, ConstraintSystem, ErrorFront, Expression, Instance, Selector,
+        TableColumn, VirtualCells,
+    },
+    poly::Rotation,
+};
+use vm::table::Tables;
+
+use crate::{
+    gadgets::{less_than::LtConfig, range_check::RangeCheckConfig},
+    padding::{capacity, padded},
+    params::BrainfuckParams,
+};
+
+/// Shared configuration handed to every table while the circuit is configured.
+pub struct TableContext<'a> {
+    pub range_check: &'a RangeCheckConfig,
+    pub params: &'a BrainfuckParams,
+}
+
+/// What the tables of a circuit are assigned from.
+pub struct Witness<'a, F: PrimeField> {
+    /// the whole trace
+    pub tables: &'a Tables<F>,
+    #[cfg(test)]
+    pub forged: &'a Forged<F>,
+}
+
+/// Witness values the soundness tests write in place of the ones computed
+/// from the trace, for the cells no field of `Tables` determines.
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Forged<F> {
+    /// inverse witnessed for the `mv` of the processor step at each clk
+    pub mvi: HashMap<u64, F>,
+}
+
+/// Builds the tuple of expressions in one slot of a table. Tuples exported
+/// for cross-table arguments must vanish on the table's padding rows.
+pub type Tuple<F> = Box<dyn Fn(&mut VirtualCells<'_, F>, usize) -> Vec<Expression<F>>>;
+/// Builds the tag of one slot, see `SlotTag`.
+pub type Tag<F> = Box<dyn Fn(&mut VirtualCells<'_, F>, usize) -> Expression<F>>;
+
+/// A tuple a table makes available to cross-table arguments, under a name of
+/// the form `"<table>.<tuple>"`, with one instance per slot.
+pub struct Export<F> {
+    pub name: &'static str,
+    pub slots: usize,
+    pub tuple: Tuple<F>,
+    /// Required to look the tuple up in an export with several slots.
+    pub tag: Option<Tag<F>>,
+}
+impl<F: PrimeField> Export<F> {
+    pub fn new(
+        name: &'static str,
+        slots: usize,
+        tuple: impl Fn(&mut VirtualCells<'_, F>, usize) -> Vec<Expression<F>> + 'static,
+    ) -> Self {
+        Self {
+            name,
+            slots,
+            tuple: Box::new(tuple),
+            tag: None,
+        }
+    }
+    pub fn tagged(
+        self,
+        tag: impl Fn(&mut VirtualCells<'_, F>, usize) -> Expression<F> + 'static,
+    ) -> Self {
+        Self {
+            tag: Some(Box::new(tag)),
+            ..self
+        }
+    }
+}
+
+/// A lookup of one exported tuple in another, referred to by name: every
+/// `input` tuple, in any slot, appears among the `table` tuples.
+///
+/// The `table` export is a union over its slots, which a single lookup
+/// cannot express. Each `input` slot is therefore looked up in every `table`
+/// slot, gated by its tag naming the one slot that must contain it.
+#[derive(Clone, Copy, Debug)]
+pub struct Argument {
+    pub name: &'static str,
+    pub input: &'static str,
+    pub table: &'static str,
+}
+impl Argument {
+    pub const fn lookup(name: &'static str, input: &'static str, table: &'static str) -> Self {
+        Self { name, input, table }
+    }
+}
+
+/// Witness naming the slot of a packed table that a tuple is looked up in.
+/// Without packing there is a single slot and no column.
+#[derive(Clone, Copy, Debug)]
+pub struct SlotTag {
+    pub column: Option<Column<Advice>>,
+    slots: usize,
+}
+impl SlotTag {
+    /// Constrains the tag to `0..slots` on rows where `q_row` is enabled.
+    pub fn configure<F: PrimeField>(
+        meta: &mut ConstraintSystem<F>,
+        q_row: Selector,
+        slots: usize,
+    ) -> Self {
+        if slots == 1 {
+            return Self {
+                column: None,
+                slots,
+            };
+        }
+        let column = meta.advice_column();
+        meta.create_gate("slot tag", |meta| {
+            let q_row = meta.query_selector(q_row);
+            let tag = meta.query_advice(column, Rotation::cur());
+            let in_range = (0..slots).fold(Expression::Constant(F::ONE), |acc, slot| {
+                acc * (tag.clone() - Expression::Constant(F::from(slot as u64)))
+            });
+            vec![q_row * in_range]
+        });
+        Self {
+            column: Some(column),
+            slots,
+        }
+    }
+
+    pub fn expr<F: PrimeField>(&self, meta: &mut VirtualCells<F>) -> Expression<F> {
+        match self.column {
+            Some(column) => meta.query_advice(column, Rotation::cur()),
+            None => Expression::Constant(F::ZERO),
+        }
+    }
+
+    pub fn assign<F: PrimeField>(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        slot: usize,
+    ) -> Result<(), ErrorFront> {
+        debug_assert!(slot < self.slots);
+        if let Some(column) = self.column {
+            region.assign_advice(
+                || "slot tag",
+                column,
+                offset,
+                || Value::known(F::from(slot as u64)),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 1 when `tag` is `slot` and 0 for every other tag in `0..slots`.
+fn slot_selector<F: PrimeField>(tag: Expression<F>, slot: usize, slots: usize) -> Expression<F> {
+    (0..slots)
+        .filter(|&other| other != slot)
+        .fold(Expression::Constant(F::ONE), |acc, other| {
+            let denominator = (F::from(slot as u64) - F::from(other as u64))
+                .invert()
+                .unwrap();
+            acc * (tag.clone() - Expression::Constant(F::from(other as u64)))
+                * Expression::Constant(denominator)
+        })
+}
+
+/// Names of a table's columns, shown by the MockProver when a constraint
+/// fails. Names are prefixed with the table name, and suffixed with the slot
+/// in packed tables: `processor.ip`, or `processor.ip[1]`.
+pub struct Annotations {
+    table: &'static str,
+    slots: usize,
+    columns: Vec<(Column<Any>, String)>,
+}
+impl Annotations {
+    fn new(table: &'static str, slots: usize) -> Self {
+        Self {
+            table,
+            slots,
+            columns: vec![],
+        }
+    }
+
+    pub fn column(&mut self, name: &str, slot: usize, column: impl Into<Column<Any>>) {
+        let name = if self.slots == 1 {
+            format!("{}.{name}", self.table)
+        } else {
+            format!("{}.{name}[{slot}]", self.table)
+        };
+        self.columns.push((column.into(), name));
+    }
+
+    /// Names a column shared by all slots.
+    pub fn shared(&mut self, name: &str, column: impl Into<Column<Any>>) {
+        self.columns
+            .push((column.into(), format!("{}.{name}", self.table)));
+    }
+
+    /// Names the columns of an `LtChip` comparing into `name`.
+    pub fn lt<F, const N_LIMBS: usize>(
+        &mut self,
+        name: &str,
+        slot: usize,
+        lt: &LtConfig<F, N_LIMBS>,
+    ) {
+        self.column(name, slot, lt.lt);
+        for (limb, &diff) in lt.diff.iter().enumerate() {
+            self.column(&format!("{name}_diff{limb}"), slot, diff);
+        }
+    }
+
+    pub fn tag(&mut self, name: &str, slot: usize, tag: &SlotTag) {
+        if let Some(column) = tag.column {
+            self.column(name, slot, column);
+        }
+    }
+}
+
+/// A fixed lookup table owned by a table, loaded once per circuit.
+pub struct FixedTable<F> {
+    pub name: &'static str,
+    pub column: TableColumn,
+    pub values: Vec<F>,
+}
+impl<F: PrimeField> FixedTable<F> {
+    pub fn load(&self, layouter: &mut impl Layouter<F>) -> Result<(), ErrorFront> {
+        layouter.assign_table(
+            || self.name,
+            |mut table| {
+                for (offset, &value) in self.values.iter().enumerate() {
+                    table.assign_cell(|| self.name, self.column, offset, || Value::known(value))?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+/// An assigned cell to be constrained against a row of the `summary`
+/// instance column.
+pub struct PublicCell<F: PrimeField> {
+    pub row: usize,
+    pub cell: AssignedCell<F, F>,
+}
+
+/// Assigns `value` to `advice` at `offset`, or copies it from the same row of
+/// `instance` when the column is public.
+///
+/// Padding rows copy their instance cell too: the permutation is fixed at
+/// keygen, where the trace is empty, so the copies must not depend on it.
+pub fn assign_public<F: PrimeField>(
+    region: &mut Region<'_, F>,
+    name: &'static str,
+    advice: Column<Advice>,
+    instance: Option<Column<Instance>>,
+    offset: usize,
+    value: F,
+) -> Result<(), ErrorFront> {
+    match instance {
+        Some(instance) => {
+            region.assign_advice_from_instance(|| name, instance, offset, advice, offset)?;
+        }
+        None => {
+            region.assign_advice(|| name, advice, offset, || Value::known(value))?;
+        }
+    }
+    Ok(())
+}
+
+/// One table of the Brainfuck AIR. A table declares its columns and gates in
+/// `configure`, the tuples it offers to other tables in `exports` and the
+/// arguments it takes part in in `arguments`; `Composer` wires them together.
+pub trait Table<F: PrimeField>: 'static {
+    /// Region name; also the prefix of the table's export names.
+    const NAME: &'static str;
+    type Config: Clone + 'static;
+    type Row: Clone;
+
+    /// Whether the table is part of a circuit with these parameters.
+    fn is_enabled(_params: &BrainfuckParams) -> bool {
+        true
+    }
+    fn configure(meta: &mut ConstraintSystem<F>, ctx: &TableContext<'_>) -> Self::Config;
+    fn exports(config: &Self::Config) -> Vec<Export<F>>;
+    /// Names every advice and instance column of the table.
+    fn annotate(config: &Self::Config, columns: &mut Annotations);
+    /// Arguments this table is responsible for. They may refer to exports of
+    /// any table, which are resolved once all tables are configured.
+    fn arguments() -> Vec<Argument> {
+        vec![]
+    }
+    fn fixed_tables(_config: &Self::Config) -> Vec<FixedTable<F>> {
+        vec![]
+    }
+    /// The table's trace rows.
+    fn rows(tables: &Tables<F>) -> &[Self::Row];
+    /// Padding row used when the trace is empty. Otherwise the table is
+    /// padded with copies of its last row.
+    fn empty_row() -> Self::Row;
+    /// Assigns the padded logical rows, each paired with its enable flag,
+    /// from offset 0 of `region`. `witness` holds the whole trace, for the
+    /// slot tags that depend on other tables.
+    fn assign(
+        config: &Self::Config,
+        region: &mut Region<'_, F>,
+        witness: &Witness<'_, F>,
+        rows: &[(Self::Row, bool)],
+    ) -> Result<Vec<PublicCell<F>>, ErrorFront>;
+}
+
+/// A configured table with its concrete types erased, so that the circuit can
+/// keep a list of them.
+pub trait ConfiguredTable<F: PrimeField> {
+    fn name(&self) -> &'static str;
+    fn fixed_tables(&self) -> Vec<FixedTable<F>>;
+    /// Number of logical rows the table's trace takes.
+    fn trace_len(&self, tables: &Tables<F>) -> usize;
+    /// Most logical rows of trace the table holds when `height` rows high.
+    fn capacity(&self, height: usize) -> usize;
+    fn assign(
+        &self,
+        region: &mut Region<'_, F>,
+        witness: &Witness<'_, F>,
+        height: usize,
+    ) -> Result<Vec<PublicCell<F>>, ErrorFront>;
+}
+
+struct Configured<F: PrimeField, T: Table<F>> {
+    config: T::Config,
+    slots: usize,
+    annotations: Vec<(Column<Any>, String)>,
+}
+
+impl<F: PrimeField, T: Table<F>> ConfiguredTable<F> for Configured<F, T> {
+    fn name(&self) -> &'static str {
+        T::NAME
+    }
+    fn fixed_tables(&self) -> Vec<FixedTable<F>> {
+        T::fixed_tables(&self.config)
+    }
+    fn trace_len(&self, tables: &Tables<F>) -> usize {
+        T::rows(tables).len()
+    }
+    fn capacity(&self, height: usize) -> usize {
+        capacity(height, self.slots)
+    }
+    fn assign(
+        &self,
+        region: &mut Region<'_, F>,
+        witness: &Witness<'_, F>,
+        height: usize,
+    ) -> Result<Vec<PublicCell<F>>, ErrorFront> {
+        let rows = padded(T::rows(witness.tables), height, self.slots, T::empty_row)?;
+        for (column, name) in &self.annotations {
+            region.name_column(|| name.as_str(), *column);
+        }
+        T::assign(&self.config, region, witness, &rows)
+    }
+}
+
+/// Configures tables one after the other and then the arguments between them.
+pub struct Composer<'a, F: PrimeField> {
+    ctx: TableContext<'a>,
+    tables: Vec<Arc<dyn ConfiguredTable<F>>>,
+    exports: HashMap<&'static str, Export<F>>,
+    arguments: Vec<Argument>,
+}
+impl<'a, F: PrimeField> Composer<'a, F> {
+    pub fn new(ctx: TableContext<'a>) -> Self {
+        Self {
+            ctx,
+            tables: vec![],
+            exports: HashMap::new(),
+            arguments: vec![],
+        }
+    }
+
+    /// Configures `T` unless it is disabled by the parameters, and returns
+    /// its configuration.
+    pub fn add<T: Table<F>>(&mut self, meta: &mut ConstraintSystem<F>) -> Option<T::Config> {
+        if !T::is_enabled(self.ctx.params) {
+            return None;
+        }
+        let config = T::configure(meta, &self.ctx);
+        let mut annotations = Annotations::new(T::NAME, self.ctx.params.steps_per_row);
+        T::annotate(&config, &mut annotations);
+        for (column, name) in &annotations.columns {
+            meta.annotate_lookup_any_column(*column, || name.as_str());
+        }
+        for export in T::exports(&config) {
+            let name = export.name;
+            let previous = self.exports.insert(name, export);
+            assert!(previous.is_none(), "tuple {name} exported twice");
+        }
+        self.arguments.extend(T::arguments());
+        self.tables.push(Arc::new(Configured::<F, T> {
+            config: config.clone(),
+            slots: self.ctx.params.steps_per_row,
+            annotations: annotations.columns,
+        }));
+        Some(config)
+    }
+
+    /// Configures the arguments declared by the added tables and returns the
+    /// tables in the order they were added.
+    pub fn finish(self, meta: &mut ConstraintSystem<F>) -> Vec<Arc<dyn ConfiguredTable<F>>> {
+        let export = |name: &str| {
+            self.exports
+                .get(name)
+                .unwrap_or_else(|| panic!("no table exports {name}"))
+        };
+        for argument in &self.arguments {
+            let input = export(argument.input);
+            let table = export(argument.table);
+            assert!(
+                table.slots == 1 || input.tag.is_some(),
+                "{}: {} needs a tag to be looked up in {}",
+                argument.name,
+                argument.input,
+                argument.table
+            );
+            for input_slot in 0..input.slots {
+                for table_slot in 0..table.slots {
+                    let name = if input.slots * table.slots == 1 {
+                        argument.name.to_string()
+                    } else {
+                        format!("{} (slot {input_slot} in {table_slot})", argument.name)
+                    };
+                    meta.lookup_any(name, |meta| {
+                        let gate = match &input.tag {
+                            Some(tag) if table.slots > 1 => {
+                                slot_selector(tag(meta, input_slot), table_slot, table.slots)
+                            }
+                            _ => Expression::Constant(F::ONE),
+                        };
+                        let input_tuple = (input.tuple)(meta, input_slot);
+                        let table_tuple = (table.tuple)(meta, table_slot);
+                        assert_eq!(
+                            input_tuple.len(),
+                            table_tuple.len(),
+                            "{}: {} and {} have different arities",
+                            argument.name,
+                            argument.input,
+                            argument.table
+                        );
+                        input_tuple
+                            .into_iter()
+                            .map(|value| gate.clone() * value)
+                            .zip(table_tuple)
+                            .collect()
+                    });
+                }
+            }
+        }
+        self.tables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gadgets::range_check::{RangeCheckChip, RANGE_CHECK_BITS};
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    /// A one-column table whose argument points at a tuple nobody exports.
+    struct Dangling;
+    impl Table<Fr> for Dangling {
+        const NAME: &'static str = "dangling";
+        type Config = halo2_proofs::plonk::Column<halo2_proofs::plonk::Advice>;
+        type Row = u64;
+
+        fn configure(meta: &mut ConstraintSystem<Fr>, _ctx: &TableContext<'_>) -> Self::Config {
+            meta.advice_column()
+        }
+        fn exports(&column: &Self::Config) -> Vec<Export<Fr>> {
+            vec![Export::new("dangling.values", 1, move |meta, _| {
+                vec![meta.query_advice(column, Rotation::cur())]
+            })]
+        }
+        fn annotate(&column: &Self::Config, columns: &mut Annotations) {
+            columns.column("value", 0, column);
+        }
+        fn arguments() -> Vec<Argument> {
+            vec![Argument::lookup(
+                "dangling lookup",
+                "dangling.values",
+                "missing.values",
+            )]
+        }
+        fn rows(_tables: &Tables<Fr>) -> &[u64] {
+            &[]
+        }
+        fn empty_row() -> u64 {
+            0
+        }
+        fn assign(
+            &column: &Self::Config,
+            region: &mut Region<'_, Fr>,
+            _witness: &Witness<'_, Fr>,
+            rows: &[(u64, bool)],
+        ) -> Result<Vec<PublicCell<Fr>>, ErrorFront> {
+            for (offset, (row, _)) in rows.iter().enumerate() {
+                region.assign_advice(
+                    || "value",
+                    column,
+                    offset,
+                    || Value::known(Fr::from(*row)),
+                )?;
+            }
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "no table exports missing.values")]
+    fn test_dangling_argument_rejected() {
+        let mut meta = ConstraintSystem::<Fr>::default();
+        let range_check = RangeCheckChip::configure(&mut meta, RANGE_CHECK_BITS);
+        let params = BrainfuckParams::default();
+        let mut composer = Composer::new(TableContext {
+            range_check: &range_check,
+            params: &params,
+        });
+        composer.add::<Dangling>(&mut meta);
+        composer.finish(&mut meta);
+    }
+}
@@ -0,0 +1,320 @@
+//! Soundness tests: targeted mutations of a valid trace, each of which the
+//! circuit must reject.
+
+use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
+use vm::{
+    interpreter::{Interpreter, ADD, PUTCHAR},
+    table::Tables,
+};
+
+use crate::{
+    circuit::BrainfuckCircuit,
+    params::BrainfuckParams,
+    public_inputs::PublicInputs,
+    sizing::{min_k, MAX_K},
+    table::Forged,
+};
+
+/// A change to a valid trace, named after what it forges.
+pub(crate) struct Mutation {
+    pub name: &'static str,
+    pub apply: fn(&mut Tables),
+    /// forges witness values that no field of `Tables` determines
+    pub forge: fn(&mut Forged<Fr>),
+}
+impl Mutation {
+    pub fn new(name: &'static str, apply: fn(&mut Tables)) -> Self {
+        Self {
+            name,
+            apply,
+            forge: |_| {},
+        }
+    }
+    /// A mutation of the witness values computed from an unchanged trace.
+    pub fn forged(name: &'static str, forge: fn(&mut Forged<Fr>)) -> Self {
+        Self {
+            name,
+            apply: |_| {},
+            forge,
+        }
+    }
+}
+
+/// Runs `code`, checks that the circuit accepts its trace and then that it
+/// rejects every mutation of it. The public inputs of a mutated trace are
+/// taken from the mutated tables, so that only the constraints between
+/// tables can catch it; the program and its length stay those of `code`.
+pub(crate) fn assert_rejected(
+    code: Vec<u8>,
+    input: Vec<Fr>,
+    params: BrainfuckParams,
+    mutations: &[Mutation],
+) {
+    let mut interpreter = Interpreter::with_config(code, input, params.vm_config());
+    interpreter.run();
+    let honest = interpreter.tables;
+    let claim = PublicInputs::from_tables(&honest, &params);
+    let k = min_k(&honest, &params, MAX_K).unwrap();
+    if let Err(failures) = verify(&honest, &Forged::default(), params, &claim, k) {
+        panic!("honest trace rejected: {failures}");
+    }
+
+    let accepted = mutations
+        .iter()
+        .filter(|mutation| {
+            let mut tables = honest.clone();
+            let mut forged = Forged::default();
+            (mutation.apply)(&mut tables);
+            (mutation.forge)(&mut forged);
+            verify(&tables, &forged, params, &claim, k).is_ok()
+        })
+        .map(|mutation| mutation.name)
+        .collect::<Vec<_>>();
+    assert!(accepted.is_empty(), "mutations accepted: {accepted:#?}");
+}
+
+/// Checks `tables`, with the witness values in `forged`, against their own
+/// public inputs, with the program of `claim`.
+fn verify(
+    tables: &Tables,
+    forged: &Forged<Fr>,
+    params: BrainfuckParams,
+    claim: &PublicInputs,
+    k: u32,
+) -> Result<(), String> {
+    let public = PublicInputs {
+        program: claim.program.clone(),
+        program_len: claim.program_len,
+        ..PublicInputs::from_tables(tables, &params)
+    };
+    let mut circuit = BrainfuckCircuit::new(tables.clone(), params);
+    circuit.forged = forged.clone();
+    // a witness that cannot even be laid out is rejected too
+    let prover = MockProver::run(k, &circuit, public.to_instances(&params))
+        .map_err(|error| error.to_string())?;
+    prover.verify().map_err(|failures| format!("{failures:#?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, CODE};
+
+    /// Cells of the tape in `params()`.
+    const TAPE_SIZE: usize = 4;
+
+    /// `CODE` with input 97: reads into cell 1, then prints 98 and 99 while
+    /// counting cell 0 down. Its 18 steps go through every opcode, both
+    /// branches of `[` and `]`, and a cell wrap-around.
+    fn program() -> (Vec<u8>, Vec<Fr>) {
+        (CODE.to_vec(), vec![Fr::from(97)])
+    }
+
+    fn params() -> BrainfuckParams {
+        BrainfuckParams {
+            cell_bits: Some(8),
+            tape_size: TAPE_SIZE,
+            ..fixtures::params()
+        }
+    }
+
+    /// One or more mutations per gate and lookup of the circuit, by table.
+    fn mutations() -> Vec<Mutation> {
+        vec![
+            // processor table: boundary, transition, terminal and halt gates
+            Mutation::new("processor clk starts at 1", |tables| {
+                tables
+                    .processor_table
+                    .iter_mut()
+                    .for_each(|row| row.clk += 1);
+                tables.memory_table.iter_mut().for_each(|row| row.clk += 1);
+            }),
+            Mutation::new("processor mv starts nonzero", |tables| {
+                tables.processor_table[0].mv = Fr::from(7);
+            }),
+            Mutation::new("processor clk skips a step", |tables| {
+                tables.processor_table[6..]
+                    .iter_mut()
+                    .for_each(|row| row.clk += 1);
+            }),
+            Mutation::new("`>` moves ip by two", |tables| {
+                tables.processor_table[3].ip = 4;
+            }),
+            Mutation::new("`>` leaves mp in place", |tables| {
+                tables.processor_table[3].mp = 0;
+            }),
+            Mutation::new("`+` adds two", |tables| {
+                tables.processor_table[1].mv = Fr::from(2);
+            }),
+            Mutation::new("`-` wraps a nonzero cell", |tables| {
+                tables.processor_table[11].mv = Fr::from(255);
+            }),
+            Mutation::new("`.` changes the cell", |tables| {
+                tables.processor_table[9].mv = Fr::from(100);
+            }),
+            Mutation::new("`[` skips the loop on a nonzero cell", |tables| {
+                let rows = &mut tables.processor_table;
+                let target = rows[5].ni as usize;
+                rows[6].ip = target;
+            }),
+            Mutation::new("`]` jump target changed", |tables| {
+                tables.processor_table[11].ni = 5;
+            }),
+            Mutation::new("halt row dropped", |tables| {
+                tables.processor_table.pop();
+            }),
+            Mutation::new("step after the halt row", |tables| {
+                let mut row = tables.processor_table.last().unwrap().clone();
+                row.clk += 1;
+                tables.processor_table.push(row);
+            }),
+            Mutation::new("halt row given an opcode", |tables| {
+                tables.processor_table.last_mut().unwrap().ci = ADD;
+            }),
+            // is-zero gadget
+            Mutation::forged("mvi forged on a nonzero cell", |forged| {
+                forged.mvi.insert(5, Fr::from(0));
+            }),
+            // decode table and range checks
+            Mutation::new("ci is not an opcode", |tables| {
+                tables.processor_table[2].ci = b'x';
+                tables.instruction_table[2].ci = b'x';
+            }),
+            Mutation::new("mp beyond the tape", |tables| {
+                tables
+                    .processor_table
+                    .iter_mut()
+                    .for_each(|row| row.mp += TAPE_SIZE);
+                tables
+                    .memory_table
+                    .iter_mut()
+                    .for_each(|row| row.mp += TAPE_SIZE);
+            }),
+            Mutation::new("mv beyond the cell width", |tables| {
+                tables.processor_table[1].mv = Fr::from(257);
+                for row in tables.memory_table.iter_mut().filter(|row| row.clk == 1) {
+                    row.mv = Fr::from(257);
+                }
+            }),
+            Mutation::new("ip beyond the program", |tables| {
+                let halt = tables.processor_table.last_mut().unwrap();
+                halt.ip = 40;
+                tables.instruction_table.last_mut().unwrap().ip = 40;
+            }),
+            // memory table: boundary, transition and order gates, lookups
+            Mutation::new("memory mv flipped", |tables| {
+                tables.memory_table[3].mv += Fr::from(1);
+            }),
+            Mutation::new("memory rows reordered", |tables| {
+                tables.memory_table.swap(1, 2);
+            }),
+            Mutation::new("memory row dropped", |tables| {
+                tables.memory_table.remove(4);
+            }),
+            Mutation::new("memory starts with a nonzero cell", |tables| {
+                tables.memory_table[0].mv = Fr::from(1);
+            }),
+            Mutation::new("memory cell read before it is written", |tables| {
+                let cell_1 = tables.memory_table.iter().position(|row| row.mp == 1);
+                tables.memory_table[cell_1.unwrap()].mv = Fr::from(97);
+            }),
+            // instruction table: transition gate and lookup
+            Mutation::new("instruction row dropped", |tables| {
+                tables.instruction_table.remove(3);
+            }),
+            Mutation::new("instruction opcode changed", |tables| {
+                tables.instruction_table[3].ci = PUTCHAR;
+            }),
+            Mutation::new("instruction jump target changed", |tables| {
+                tables.instruction_table[12].ni = 3;
+            }),
+            // input table: order and length gates, lookups, public values
+            Mutation::new("input value changed", |tables| {
+                tables.input_table[0].value = Fr::from(98);
+            }),
+            Mutation::new("input row dropped", |tables| {
+                tables.input_table.clear();
+            }),
+            Mutation::new("input row at another step", |tables| {
+                tables.input_table[0].clk = 4;
+            }),
+            Mutation::new("input row duplicated", |tables| {
+                let row = tables.input_table[0].clone();
+                tables.input_table.push(row);
+            }),
+            // output table: order and length gates, lookups, public values
+            Mutation::new("output value changed", |tables| {
+                tables.output_table[1].value = Fr::from(0);
+            }),
+            Mutation::new("output row dropped", |tables| {
+                tables.output_table.pop();
+            }),
+            Mutation::new("output rows reordered", |tables| {
+                tables.output_table.swap(0, 1);
+            }),
+            Mutation::new("output row duplicated", |tables| {
+                let row = tables.output_table[1].clone();
+                tables.output_table.push(row);
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_mutations_rejected() {
+        let (code, input) = program();
+        assert_rejected(code, input, params(), &mutations());
+    }
+
+    #[test]
+    fn test_packed_mutations_rejected() {
+        let (code, input) = program();
+        for steps_per_row in [2, 3] {
+            let params = BrainfuckParams {
+                steps_per_row,
+                ..params()
+            };
+            assert_rejected(code.clone(), input.clone(), params, &mutations());
+        }
+    }
+
+    /// Traces consistent between tables but not with the public program.
+    fn program_mutations() -> Vec<Mutation> {
+        vec![
+            // ni of the `<` at ip 4 only shows in lookups
+            Mutation::new("instruction ni is not the next ci", |tables| {
+                tables.instruction_table[4].ni = ADD;
+                for row in &mut tables.processor_table {
+                    if row.ip == 4 {
+                        row.ni = ADD;
+                    }
+                }
+            }),
+            Mutation::new("instruction position repeated", |tables| {
+                let row = tables.instruction_table[2].clone();
+                tables.instruction_table.insert(2, row);
+            }),
+            Mutation::new("instruction ip starts at 1", |tables| {
+                for row in &mut tables.instruction_table {
+                    row.ip += 1;
+                }
+                for row in &mut tables.processor_table {
+                    row.ip += 1;
+                }
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_public_program_mutations_rejected() {
+        let (code, input) = program();
+        for steps_per_row in [1, 2] {
+            let params = BrainfuckParams {
+                steps_per_row,
+                expose_program: true,
+                ..params()
+            };
+            let mutations = [mutations(), program_mutations()].concat();
+            assert_rejected(code.clone(), input.clone(), params, &mutations);
+        }
+    }
+}